@@ -0,0 +1,5 @@
+pub(crate) use set_role::set_role;
+pub(crate) use world_stats::world_stats;
+
+pub(crate) mod set_role;
+pub(crate) mod world_stats;
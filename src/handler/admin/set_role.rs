@@ -0,0 +1,40 @@
+use actix_web::web::{Data, Json};
+use rorm::{query, update, Database, Model};
+use serde::{Deserialize, Serialize};
+
+use crate::handler::frontend;
+use crate::handler::frontend::Errors;
+use crate::models::db::{Role, User};
+
+#[derive(Deserialize)]
+pub(crate) struct SetRoleRequest {
+    username: String,
+    role: Role,
+}
+
+#[derive(Serialize)]
+pub(crate) struct SetRoleResponse {
+    success: bool,
+}
+
+pub(crate) async fn set_role(
+    db: Data<Database>,
+    req: Json<SetRoleRequest>,
+) -> frontend::Result<Json<SetRoleResponse>> {
+    if query!(&db, User)
+        .condition(User::F.username.equals(&req.username))
+        .optional()
+        .await?
+        .is_none()
+    {
+        return Err(Errors::UserNotFound);
+    }
+
+    update!(&db, User)
+        .set(User::F.role, req.role)
+        .condition(User::F.username.equals(&req.username))
+        .exec()
+        .await?;
+
+    Ok(Json(SetRoleResponse { success: true }))
+}
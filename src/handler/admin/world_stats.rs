@@ -0,0 +1,18 @@
+use actix_web::web::{Data, Json};
+use serde::Serialize;
+
+use crate::world::cache::CacheStats;
+use crate::world::TileCache;
+
+#[derive(Serialize)]
+pub(crate) struct WorldStatsResponse {
+    success: bool,
+    tile_cache: CacheStats,
+}
+
+pub(crate) async fn world_stats(cache: Data<TileCache>) -> Json<WorldStatsResponse> {
+    Json(WorldStatsResponse {
+        success: true,
+        tile_cache: cache.stats(),
+    })
+}
@@ -0,0 +1,138 @@
+use actix_web::web::{Data, Json, Path};
+use rorm::{delete, insert, query, Database, ForeignModel, Model};
+use serde::{Deserialize, Serialize};
+
+use crate::handler::frontend;
+use crate::handler::frontend::Errors;
+use crate::helper::api_tokens::generate_token;
+use crate::helper::{AuthenticatedUser, AuthenticationMethod, TokenScope};
+use crate::models::db::{ApiToken, ApiTokenInsert};
+
+#[derive(Deserialize)]
+pub(crate) struct CreateApiTokenRequest {
+    name: String,
+    scopes: Vec<TokenScope>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct CreateApiTokenResponse {
+    success: bool,
+    id: i64,
+    /// The plain token, it can't be retrieved again
+    token: String,
+}
+
+pub(crate) async fn create_api_token(
+    db: Data<Database>,
+    user: AuthenticatedUser,
+    req: Json<CreateApiTokenRequest>,
+) -> frontend::Result<Json<CreateApiTokenResponse>> {
+    user.require_scope(TokenScope::Account)?;
+
+    let CreateApiTokenRequest { name, scopes } = req.into_inner();
+    let name = name.trim().to_string();
+    if name.is_empty() || name.len() > 255 {
+        return Err(Errors::InvalidTokenName);
+    }
+
+    // A token must not be able to create a token with more permissions than itself
+    if let AuthenticationMethod::Token { scopes: own, .. } = &user.method {
+        if scopes.iter().any(|scope| !own.contains(scope)) {
+            return Err(Errors::MissingScope);
+        }
+    }
+    let mut unique_scopes = Vec::with_capacity(scopes.len());
+    for scope in scopes {
+        if !unique_scopes.contains(&scope) {
+            unique_scopes.push(scope);
+        }
+    }
+
+    let (token, token_hash) = generate_token();
+    let id = insert!(&db, ApiTokenInsert)
+        .single(&ApiTokenInsert {
+            user: ForeignModel::Key(user.username),
+            name,
+            token_hash,
+            scopes: TokenScope::format_list(&unique_scopes),
+            last_used: None,
+        })
+        .await?;
+
+    Ok(Json(CreateApiTokenResponse {
+        success: true,
+        id,
+        token,
+    }))
+}
+
+#[derive(Serialize)]
+pub(crate) struct ApiTokenEntry {
+    id: i64,
+    name: String,
+    scopes: Vec<TokenScope>,
+    created_at: chrono::NaiveDateTime,
+    last_used: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ListApiTokensResponse {
+    success: bool,
+    tokens: Vec<ApiTokenEntry>,
+}
+
+pub(crate) async fn list_api_tokens(
+    db: Data<Database>,
+    user: AuthenticatedUser,
+) -> frontend::Result<Json<ListApiTokensResponse>> {
+    user.require_scope(TokenScope::Account)?;
+
+    let tokens = query!(&db, ApiToken)
+        .condition(ApiToken::F.user.equals(&user.username))
+        .all()
+        .await?
+        .into_iter()
+        .map(|token| ApiTokenEntry {
+            id: token.id,
+            scopes: TokenScope::parse_list(&token.scopes),
+            name: token.name,
+            created_at: token.created_at,
+            last_used: token.last_used,
+        })
+        .collect();
+
+    Ok(Json(ListApiTokensResponse {
+        success: true,
+        tokens,
+    }))
+}
+
+#[derive(Serialize)]
+pub(crate) struct RevokeApiTokenResponse {
+    success: bool,
+}
+
+pub(crate) async fn revoke_api_token(
+    db: Data<Database>,
+    user: AuthenticatedUser,
+    path: Path<i64>,
+) -> frontend::Result<Json<RevokeApiTokenResponse>> {
+    user.require_scope(TokenScope::Account)?;
+    let id = path.into_inner();
+
+    query!(&db, ApiToken)
+        .condition(ApiToken::F.id.equals(id))
+        .optional()
+        .await?
+        .filter(|token| match &token.user {
+            ForeignModel::Key(username) => username == &user.username,
+            ForeignModel::Instance(owner) => owner.username == user.username,
+        })
+        .ok_or(Errors::TokenNotFound)?;
+
+    delete!(&db, ApiToken)
+        .condition(ApiToken::F.id.equals(id))
+        .await?;
+
+    Ok(Json(RevokeApiTokenResponse { success: true }))
+}
@@ -0,0 +1,61 @@
+use actix_toolbox::tb_middleware::Session;
+use actix_web::web::{Data, Json};
+use rorm::{query, Database, Model};
+use serde::{Deserialize, Serialize};
+
+use crate::handler::frontend;
+use crate::handler::frontend::Errors;
+use crate::helper::{
+    replace_password, AuthenticatedUser, AuthenticationMethod, PasswordHashing, TokenScope,
+};
+use crate::models::db::User;
+
+#[derive(Deserialize)]
+pub(crate) struct ChangePasswordRequest {
+    old_password: String,
+    new_password: String,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ChangePasswordResponse {
+    success: bool,
+}
+
+pub(crate) async fn change_password(
+    db: Data<Database>,
+    hashing: Data<PasswordHashing>,
+    session: Session,
+    user: AuthenticatedUser,
+    req: Json<ChangePasswordRequest>,
+) -> frontend::Result<Json<ChangePasswordResponse>> {
+    user.require_scope(TokenScope::Account)?;
+    let username = &user.username;
+
+    let db_user = query!(&db, User)
+        .condition(User::F.username.equals(username))
+        .optional()
+        .await?
+        .ok_or(Errors::Unauthenticated)?;
+
+    if !hashing.verify(&req.old_password, &db_user.password_hash)? {
+        return Err(Errors::WrongPassword);
+    }
+    if req.new_password.is_empty() {
+        return Err(Errors::InvalidPassword);
+    }
+
+    let password_hash = hashing.hash(&req.new_password)?;
+
+    // Log out every other session and revoke the api tokens,
+    // the current session is kept under a new key
+    let current = match &user.method {
+        AuthenticationMethod::Session { id } => Some(id.as_str()),
+        AuthenticationMethod::Token { .. } => None,
+    };
+    replace_password(&db, username, &password_hash, current).await?;
+    if current.is_some() {
+        session.renew();
+    }
+
+    Ok(Json(ChangePasswordResponse { success: true }))
+}
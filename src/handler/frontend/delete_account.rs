@@ -0,0 +1,49 @@
+use actix_toolbox::tb_middleware::Session;
+use actix_web::web::{Data, Json};
+use rorm::{delete, query, Database, Model};
+use serde::{Deserialize, Serialize};
+
+use crate::handler::frontend;
+use crate::handler::frontend::Errors;
+use crate::helper::{AuthenticatedUser, PasswordHashing, TokenScope};
+use crate::models::db::User;
+
+#[derive(Deserialize)]
+pub(crate) struct DeleteAccountRequest {
+    password: String,
+}
+
+#[derive(Serialize)]
+pub(crate) struct DeleteAccountResponse {
+    success: bool,
+}
+
+pub(crate) async fn delete_account(
+    db: Data<Database>,
+    hashing: Data<PasswordHashing>,
+    session: Session,
+    user: AuthenticatedUser,
+    req: Json<DeleteAccountRequest>,
+) -> frontend::Result<Json<DeleteAccountResponse>> {
+    user.require_scope(TokenScope::Account)?;
+    let username = user.username;
+
+    let db_user = query!(&db, User)
+        .condition(User::F.username.equals(&username))
+        .optional()
+        .await?
+        .ok_or(Errors::Unauthenticated)?;
+
+    if !hashing.verify(&req.password, &db_user.password_hash)? {
+        return Err(Errors::WrongPassword);
+    }
+
+    // Sessions, tokens and recovery codes are deleted by the foreign keys' cascade
+    delete!(&db, User)
+        .condition(User::F.username.equals(&username))
+        .await?;
+
+    session.purge();
+
+    Ok(Json(DeleteAccountResponse { success: true }))
+}
@@ -1,12 +1,14 @@
 use actix_toolbox::tb_middleware::Session;
 use actix_web::web::{Data, Json};
-use argon2::password_hash::Error;
-use argon2::{Argon2, PasswordHash, PasswordVerifier};
-use rorm::{query, Database, Model};
+use actix_web::HttpRequest;
+use chrono::{Duration, NaiveDateTime, Utc};
+use log::warn;
+use rorm::{query, update, Database, Model};
 use serde::{Deserialize, Serialize};
 
 use crate::handler::frontend;
 use crate::handler::frontend::Errors;
+use crate::helper::{start_session, LoginThrottle, PasswordHashing};
 use crate::models::db::User;
 
 #[derive(Deserialize)]
@@ -18,40 +20,91 @@ pub(crate) struct LoginRequest {
 #[derive(Serialize)]
 pub(crate) struct LoginResponse {
     success: bool,
+    /// The login has to be finished with a second factor
+    second_factor_required: bool,
 }
 
-const PW: &[u8] = "super-secure-password".as_bytes();
-const HASH: &str = "$argon2id$v=19$m=16,t=2,p=1$RG9RY0ZxeWs0N3RjTDZ5cQ$FDFDJzWVOJiBvE/Sg9HjMw";
+/// A login which is waiting for its second factor
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PendingLogin {
+    pub(crate) username: String,
+    pub(crate) expires_at: NaiveDateTime,
+}
+
+impl PendingLogin {
+    /// Session key of the pending login
+    pub(crate) const KEY: &'static str = "pending_login";
+}
+
+/// Time the user has to enter the second factor
+const SECOND_FACTOR_TIMEOUT: i64 = 5 * 60;
 
 pub(crate) async fn login(
     db: Data<Database>,
+    throttle: Data<LoginThrottle>,
+    hashing: Data<PasswordHashing>,
     session: Session,
+    request: HttpRequest,
     req: Json<LoginRequest>,
 ) -> frontend::Result<Json<LoginResponse>> {
-    let hasher = Argon2::default();
+    let ip = throttle.client_ip(&request);
+    throttle
+        .check(&req.username, ip)
+        .map_err(Errors::LoginLocked)?;
 
     let Some(user) = query!(&db, User)
         .condition(User::F.username.equals(&req.username))
         .optional()
         .await? else {
         // Run hash check to protect against enumeration via request time
-        let h = PasswordHash::new(HASH)?;
-        hasher.verify_password(PW, &h)?;
+        hashing.verify_dummy(&req.password)?;
+        throttle.failed(&req.username, ip);
         return Err(Errors::LoginFailed);
     };
 
-    if let Err(err) = hasher.verify_password(
-        req.password.as_bytes(),
-        &PasswordHash::new(&user.password_hash)?,
-    ) {
-        return match err {
-            Error::Password => Err(Errors::LoginFailed),
-            _ => Err(err)?,
-        };
+    if !hashing.verify(&req.password, &user.password_hash)? {
+        throttle.failed(&req.username, ip);
+        return Err(Errors::LoginFailed);
+    }
+
+    // Upgrade hashes created with weaker parameters while the plain password is known
+    if hashing.needs_rehash(&user.password_hash) {
+        let password_hash = hashing.hash(&req.password)?;
+        if let Err(err) = update!(&db, User)
+            .set(User::F.password_hash, &password_hash)
+            .condition(User::F.username.equals(&user.username))
+            .exec()
+            .await
+        {
+            warn!(
+                "Could not upgrade password hash of {}: {err}",
+                user.username
+            );
+        }
+    }
+
+    if user.totp_secret.is_some() {
+        session.insert(
+            PendingLogin::KEY,
+            PendingLogin {
+                username: user.username,
+                expires_at: Utc::now().naive_utc() + Duration::seconds(SECOND_FACTOR_TIMEOUT),
+            },
+        )?;
+
+        return Ok(Json(LoginResponse {
+            success: true,
+            second_factor_required: true,
+        }));
     }
 
-    session.insert("logged_in", true)?;
-    session.insert("user", &user.username)?;
+    // With a second factor the failures are only cleared once it has been checked,
+    // otherwise knowing the password would reset the counter between guesses
+    throttle.succeeded(&user.username);
+    start_session(&db, &session, &request, &user.username).await?;
 
-    Ok(Json(LoginResponse { success: true }))
+    Ok(Json(LoginResponse {
+        success: true,
+        second_factor_required: false,
+    }))
 }
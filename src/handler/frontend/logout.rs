@@ -1,15 +1,27 @@
 use actix_toolbox::tb_middleware::Session;
-use actix_web::web::Json;
+use actix_web::web::{Data, Json};
+use rorm::{delete, Database, Model};
 use serde::Serialize;
 
 use crate::handler::frontend;
+use crate::helper::{AuthenticatedUser, AuthenticationMethod};
+use crate::models::db::UserSession;
 
 #[derive(Serialize)]
 pub(crate) struct LogoutResponse {
     success: bool,
 }
 
-pub(crate) async fn logout(session: Session) -> frontend::Result<Json<LogoutResponse>> {
+pub(crate) async fn logout(
+    db: Data<Database>,
+    session: Session,
+    user: AuthenticatedUser,
+) -> frontend::Result<Json<LogoutResponse>> {
+    if let AuthenticationMethod::Session { id } = &user.method {
+        delete!(&db, UserSession)
+            .condition(UserSession::F.id.equals(id))
+            .await?;
+    }
     session.purge();
 
     Ok(Json(LogoutResponse { success: true }))
@@ -0,0 +1,71 @@
+use actix_web::web::{Data, Json};
+use rorm::{query, update, Database, Model};
+use serde::{Deserialize, Serialize};
+
+use crate::handler::frontend;
+use crate::handler::frontend::Errors;
+use crate::helper::{AuthenticatedUser, DisplayNamePolicy, TokenScope};
+use crate::models::db::{Role, User};
+
+#[derive(Serialize)]
+pub(crate) struct MeResponse {
+    success: bool,
+    username: String,
+    display_name: String,
+    role: Role,
+    created_at: chrono::NaiveDateTime,
+}
+
+pub(crate) async fn get_me(
+    db: Data<Database>,
+    user: AuthenticatedUser,
+) -> frontend::Result<Json<MeResponse>> {
+    user.require_scope(TokenScope::Profile)?;
+
+    let user = query!(&db, User)
+        .condition(User::F.username.equals(&user.username))
+        .optional()
+        .await?
+        .ok_or(Errors::Unauthenticated)?;
+
+    Ok(Json(MeResponse {
+        success: true,
+        username: user.username,
+        display_name: user.display_name,
+        role: user.role,
+        created_at: user.created_at,
+    }))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct UpdateMeRequest {
+    display_name: Option<String>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct UpdateMeResponse {
+    success: bool,
+}
+
+pub(crate) async fn update_me(
+    db: Data<Database>,
+    display_names: Data<DisplayNamePolicy>,
+    user: AuthenticatedUser,
+    req: Json<UpdateMeRequest>,
+) -> frontend::Result<Json<UpdateMeResponse>> {
+    user.require_scope(TokenScope::Profile)?;
+
+    if let Some(display_name) = &req.display_name {
+        let display_name = display_names
+            .normalize(display_name)
+            .ok_or(Errors::InvalidDisplayName)?;
+
+        update!(&db, User)
+            .set(User::F.display_name, &display_name)
+            .condition(User::F.username.equals(&user.username))
+            .exec()
+            .await?;
+    }
+
+    Ok(Json(UpdateMeResponse { success: true }))
+}
@@ -1,4 +1,5 @@
 use std::fmt::{Debug, Display, Formatter};
+use std::time::Duration;
 
 use actix_toolbox::tb_middleware::actix_session::{SessionGetError, SessionInsertError};
 use actix_web::body::BoxBody;
@@ -7,17 +8,48 @@ use log::error;
 use serde::Serialize;
 use serde_repr::Serialize_repr;
 
+pub(crate) use api_tokens::{create_api_token, list_api_tokens, revoke_api_token};
+pub(crate) use change_password::change_password;
+pub(crate) use delete_account::delete_account;
 pub(crate) use login::login;
 pub(crate) use logout::logout;
+pub(crate) use me::{get_me, update_me};
+pub(crate) use register::register;
+pub(crate) use second_factor::login_second_factor;
+pub(crate) use sessions::{list_sessions, revoke_all_sessions, revoke_session};
+pub(crate) use totp::{totp_confirm, totp_disable, totp_enroll};
 
+pub(crate) mod api_tokens;
+pub(crate) mod change_password;
+pub(crate) mod delete_account;
 pub(crate) mod login;
 pub(crate) mod logout;
+pub(crate) mod me;
+pub(crate) mod register;
+pub(crate) mod second_factor;
+pub(crate) mod sessions;
+pub(crate) mod totp;
 
 #[derive(Serialize_repr)]
 #[repr(u16)]
 pub(crate) enum ErrorStatusCode {
     LoginFailed = 100,
     Unauthenticated = 101,
+    UsernameAlreadyOccupied = 102,
+    InvalidUsername = 103,
+    InvalidDisplayName = 104,
+    InvalidPassword = 105,
+    WrongPassword = 106,
+    MissingPrivileges = 107,
+    UserNotFound = 108,
+    LoginLocked = 109,
+    SecondFactorInvalid = 110,
+    TotpAlreadyEnabled = 111,
+    TotpNotEnrolled = 112,
+    InvalidTokenName = 113,
+    TokenNotFound = 114,
+    MissingScope = 115,
+    SessionNotFound = 116,
     DatabaseError = 500,
     InternalServerError = 501,
     SessionError = 502,
@@ -28,6 +60,9 @@ pub(crate) struct ErrorResponse {
     success: bool,
     status_code: ErrorStatusCode,
     message: String,
+    /// Seconds until the request may be retried
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after: Option<u64>,
 }
 
 impl ErrorResponse {
@@ -36,6 +71,7 @@ impl ErrorResponse {
             success: false,
             status_code,
             message,
+            retry_after: None,
         }
     }
 }
@@ -52,6 +88,21 @@ pub(crate) enum SessionErrors {
 pub(crate) enum Errors {
     LoginFailed,
     Unauthenticated,
+    UsernameAlreadyOccupied,
+    InvalidUsername,
+    InvalidDisplayName,
+    InvalidPassword,
+    WrongPassword,
+    MissingPrivileges,
+    UserNotFound,
+    LoginLocked(Duration),
+    SecondFactorInvalid,
+    TotpAlreadyEnabled,
+    TotpNotEnrolled,
+    InvalidTokenName,
+    TokenNotFound,
+    MissingScope,
+    SessionNotFound,
     DatabaseError(rorm::Error),
     HashError(argon2::password_hash::Error),
     SessionError(SessionErrors),
@@ -65,6 +116,21 @@ impl Display for Errors {
             Errors::SessionError(_) => write!(f, "Error while accessing session"),
             Errors::LoginFailed => write!(f, "Invalid username / password"),
             Errors::Unauthenticated => write!(f, "Unauthenticated"),
+            Errors::UsernameAlreadyOccupied => write!(f, "Username is already occupied"),
+            Errors::InvalidUsername => write!(f, "Invalid username"),
+            Errors::InvalidDisplayName => write!(f, "Invalid display name"),
+            Errors::InvalidPassword => write!(f, "Invalid password"),
+            Errors::WrongPassword => write!(f, "Wrong password"),
+            Errors::MissingPrivileges => write!(f, "Missing privileges"),
+            Errors::UserNotFound => write!(f, "User not found"),
+            Errors::LoginLocked(_) => write!(f, "Too many failed login attempts"),
+            Errors::SecondFactorInvalid => write!(f, "Invalid second factor"),
+            Errors::TotpAlreadyEnabled => write!(f, "Two-factor authentication is already enabled"),
+            Errors::TotpNotEnrolled => write!(f, "Two-factor authentication is not enrolled"),
+            Errors::InvalidTokenName => write!(f, "Invalid token name"),
+            Errors::TokenNotFound => write!(f, "Token not found"),
+            Errors::MissingScope => write!(f, "The api token is missing a required scope"),
+            Errors::SessionNotFound => write!(f, "Session not found"),
         }
     }
 }
@@ -107,6 +173,67 @@ impl actix_web::ResponseError for Errors {
                 ErrorStatusCode::Unauthenticated,
                 self.to_string(),
             )),
+            Errors::UsernameAlreadyOccupied => HttpResponse::Ok().json(ErrorResponse::new(
+                ErrorStatusCode::UsernameAlreadyOccupied,
+                self.to_string(),
+            )),
+            Errors::InvalidUsername => HttpResponse::Ok().json(ErrorResponse::new(
+                ErrorStatusCode::InvalidUsername,
+                self.to_string(),
+            )),
+            Errors::InvalidDisplayName => HttpResponse::Ok().json(ErrorResponse::new(
+                ErrorStatusCode::InvalidDisplayName,
+                self.to_string(),
+            )),
+            Errors::InvalidPassword => HttpResponse::Ok().json(ErrorResponse::new(
+                ErrorStatusCode::InvalidPassword,
+                self.to_string(),
+            )),
+            Errors::WrongPassword => HttpResponse::Ok().json(ErrorResponse::new(
+                ErrorStatusCode::WrongPassword,
+                self.to_string(),
+            )),
+            Errors::MissingPrivileges => HttpResponse::Ok().json(ErrorResponse::new(
+                ErrorStatusCode::MissingPrivileges,
+                self.to_string(),
+            )),
+            Errors::UserNotFound => HttpResponse::Ok().json(ErrorResponse::new(
+                ErrorStatusCode::UserNotFound,
+                self.to_string(),
+            )),
+            Errors::LoginLocked(remaining) => HttpResponse::Ok().json(ErrorResponse {
+                // Round up, so clients don't retry a moment too early
+                retry_after: Some(remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0)),
+                ..ErrorResponse::new(ErrorStatusCode::LoginLocked, self.to_string())
+            }),
+            Errors::SecondFactorInvalid => HttpResponse::Ok().json(ErrorResponse::new(
+                ErrorStatusCode::SecondFactorInvalid,
+                self.to_string(),
+            )),
+            Errors::TotpAlreadyEnabled => HttpResponse::Ok().json(ErrorResponse::new(
+                ErrorStatusCode::TotpAlreadyEnabled,
+                self.to_string(),
+            )),
+            Errors::TotpNotEnrolled => HttpResponse::Ok().json(ErrorResponse::new(
+                ErrorStatusCode::TotpNotEnrolled,
+                self.to_string(),
+            )),
+            Errors::InvalidTokenName => HttpResponse::Ok().json(ErrorResponse::new(
+                ErrorStatusCode::InvalidTokenName,
+                self.to_string(),
+            )),
+            Errors::TokenNotFound => HttpResponse::Ok().json(ErrorResponse::new(
+                ErrorStatusCode::TokenNotFound,
+                self.to_string(),
+            )),
+            Errors::MissingScope => HttpResponse::Ok().json(ErrorResponse::new(
+                ErrorStatusCode::MissingScope,
+                self.to_string(),
+            )),
+            Errors::SessionNotFound => HttpResponse::Ok().json(ErrorResponse::new(
+                ErrorStatusCode::SessionNotFound,
+                self.to_string(),
+            )),
         }
     }
 }
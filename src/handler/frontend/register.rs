@@ -0,0 +1,83 @@
+use actix_toolbox::tb_middleware::Session;
+use actix_web::web::{Data, Json};
+use actix_web::HttpRequest;
+use rorm::{insert, query, Database, Model};
+use serde::{Deserialize, Serialize};
+
+use crate::handler::frontend;
+use crate::handler::frontend::Errors;
+use crate::helper::{start_session, valid_username, DisplayNamePolicy, PasswordHashing};
+use crate::models::db::{Role, User, UserInsert};
+
+#[derive(Deserialize)]
+pub(crate) struct RegisterRequest {
+    username: String,
+    display_name: String,
+    password: String,
+}
+
+#[derive(Serialize)]
+pub(crate) struct RegisterResponse {
+    success: bool,
+}
+
+pub(crate) async fn register(
+    db: Data<Database>,
+    hashing: Data<PasswordHashing>,
+    display_names: Data<DisplayNamePolicy>,
+    session: Session,
+    request: HttpRequest,
+    req: Json<RegisterRequest>,
+) -> frontend::Result<Json<RegisterResponse>> {
+    let RegisterRequest {
+        username,
+        display_name,
+        password,
+    } = req.into_inner();
+
+    if !valid_username(&username) {
+        return Err(Errors::InvalidUsername);
+    }
+    let display_name = display_names
+        .normalize(&display_name)
+        .ok_or(Errors::InvalidDisplayName)?;
+    if password.is_empty() {
+        return Err(Errors::InvalidPassword);
+    }
+
+    if username_taken(&db, &username).await? {
+        return Err(Errors::UsernameAlreadyOccupied);
+    }
+
+    let password_hash = hashing.hash(&password)?;
+
+    if let Err(err) = insert!(&db, UserInsert)
+        .single(&UserInsert {
+            username: username.clone(),
+            display_name,
+            password_hash,
+            role: Role::Player,
+        })
+        .await
+    {
+        // A concurrent registration may have taken the name after the check above,
+        // the insert then fails on the primary key
+        return Err(if username_taken(&db, &username).await? {
+            Errors::UsernameAlreadyOccupied
+        } else {
+            err.into()
+        });
+    }
+
+    start_session(&db, &session, &request, &username).await?;
+
+    Ok(Json(RegisterResponse { success: true }))
+}
+
+async fn username_taken(db: &Database, username: &str) -> Result<bool, rorm::Error> {
+    Ok(query!(db, User)
+        .condition(User::F.username.equals(username))
+        .optional()
+        .await?
+        .is_some())
+}
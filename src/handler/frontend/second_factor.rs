@@ -0,0 +1,122 @@
+use actix_toolbox::tb_middleware::Session;
+use actix_web::web::{Data, Json};
+use actix_web::HttpRequest;
+use chrono::Utc;
+use rorm::{and, delete, query, update, Database, Model};
+use serde::{Deserialize, Serialize};
+
+use crate::handler::frontend;
+use crate::handler::frontend::login::PendingLogin;
+use crate::handler::frontend::Errors;
+use crate::helper::{start_session, totp, LoginThrottle, PasswordHashing};
+use crate::models::db::{RecoveryCode, User};
+
+#[derive(Deserialize)]
+pub(crate) struct SecondFactorRequest {
+    /// Code generated by the authenticator app
+    code: Option<String>,
+    /// One of the recovery codes, if the authenticator is not available
+    recovery_code: Option<String>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct SecondFactorResponse {
+    success: bool,
+}
+
+/// Second step of the login for users with two-factor authentication
+pub(crate) async fn login_second_factor(
+    db: Data<Database>,
+    throttle: Data<LoginThrottle>,
+    hashing: Data<PasswordHashing>,
+    session: Session,
+    request: HttpRequest,
+    req: Json<SecondFactorRequest>,
+) -> frontend::Result<Json<SecondFactorResponse>> {
+    let pending: PendingLogin = session
+        .get(PendingLogin::KEY)?
+        .ok_or(Errors::Unauthenticated)?;
+    if pending.expires_at < Utc::now().naive_utc() {
+        session.remove(PendingLogin::KEY);
+        return Err(Errors::Unauthenticated);
+    }
+
+    let ip = throttle.client_ip(&request);
+    throttle
+        .check(&pending.username, ip)
+        .map_err(Errors::LoginLocked)?;
+
+    let user = query!(&db, User)
+        .condition(User::F.username.equals(&pending.username))
+        .optional()
+        .await?
+        .ok_or(Errors::Unauthenticated)?;
+
+    let valid = match (&req.code, &req.recovery_code, &user.totp_secret) {
+        (Some(code), _, Some(secret)) => use_totp_code(&db, &user, secret, code).await?,
+        (None, Some(recovery_code), _) => {
+            use_recovery_code(&db, &hashing, &user.username, recovery_code).await?
+        }
+        _ => false,
+    };
+    if !valid {
+        throttle.failed(&pending.username, ip);
+        return Err(Errors::SecondFactorInvalid);
+    }
+    throttle.succeeded(&user.username);
+
+    session.remove(PendingLogin::KEY);
+    start_session(&db, &session, &request, &user.username).await?;
+
+    Ok(Json(SecondFactorResponse { success: true }))
+}
+
+/// Check a TOTP code and remember its period, so the code can't be used again
+async fn use_totp_code(
+    db: &Database,
+    user: &User,
+    secret: &str,
+    code: &str,
+) -> frontend::Result<bool> {
+    let last_counter = u64::try_from(user.totp_last_counter).unwrap_or_default();
+    let Some(counter) = totp::verify_code(secret, code, last_counter) else {
+        return Ok(false);
+    };
+    let counter = i64::try_from(counter).unwrap_or(i64::MAX);
+
+    // The condition lets only one of several concurrent logins with the same code succeed
+    let updated = update!(db, User)
+        .set(User::F.totp_last_counter, counter)
+        .condition(and!(
+            User::F.username.equals(&user.username),
+            User::F.totp_last_counter.less_than(counter)
+        ))
+        .exec()
+        .await?;
+    Ok(updated > 0)
+}
+
+/// Check a recovery code and delete it, if it matches
+async fn use_recovery_code(
+    db: &Database,
+    hashing: &PasswordHashing,
+    username: &str,
+    recovery_code: &str,
+) -> frontend::Result<bool> {
+    let recovery_code = recovery_code.trim().replace('-', "").to_ascii_lowercase();
+
+    let codes = query!(db, RecoveryCode)
+        .condition(RecoveryCode::F.user.equals(username))
+        .all()
+        .await?;
+    for code in codes {
+        if hashing.verify(&recovery_code, &code.code_hash)? {
+            delete!(db, RecoveryCode)
+                .condition(RecoveryCode::F.id.equals(code.id))
+                .await?;
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
@@ -0,0 +1,103 @@
+use actix_web::web::{Data, Json, Path};
+use rorm::{and, delete, query, Database, Model};
+use serde::Serialize;
+
+use crate::handler::frontend;
+use crate::handler::frontend::Errors;
+use crate::helper::sessions::expired_before;
+use crate::helper::{delete_user_sessions, AuthenticatedUser, AuthenticationMethod, TokenScope};
+use crate::models::db::UserSession;
+
+/// Id of the session the request was made with, if any
+fn current_session(user: &AuthenticatedUser) -> Option<&str> {
+    match &user.method {
+        AuthenticationMethod::Session { id } => Some(id),
+        AuthenticationMethod::Token { .. } => None,
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct SessionEntry {
+    id: String,
+    created_at: chrono::NaiveDateTime,
+    last_seen: chrono::NaiveDateTime,
+    user_agent: Option<String>,
+    ip: Option<String>,
+    /// This is the session the request was made with
+    current: bool,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ListSessionsResponse {
+    success: bool,
+    sessions: Vec<SessionEntry>,
+}
+
+pub(crate) async fn list_sessions(
+    db: Data<Database>,
+    user: AuthenticatedUser,
+) -> frontend::Result<Json<ListSessionsResponse>> {
+    user.require_scope(TokenScope::Account)?;
+    let current = current_session(&user);
+
+    let sessions = query!(&db, UserSession)
+        .condition(and!(
+            UserSession::F.user.equals(&user.username),
+            UserSession::F.last_seen.greater_or_equals(expired_before())
+        ))
+        .all()
+        .await?
+        .into_iter()
+        .map(|session| SessionEntry {
+            current: Some(session.id.as_str()) == current,
+            id: session.id,
+            created_at: session.created_at,
+            last_seen: session.last_seen,
+            user_agent: session.user_agent,
+            ip: session.ip,
+        })
+        .collect();
+
+    Ok(Json(ListSessionsResponse {
+        success: true,
+        sessions,
+    }))
+}
+
+#[derive(Serialize)]
+pub(crate) struct RevokeSessionResponse {
+    success: bool,
+}
+
+pub(crate) async fn revoke_session(
+    db: Data<Database>,
+    user: AuthenticatedUser,
+    path: Path<String>,
+) -> frontend::Result<Json<RevokeSessionResponse>> {
+    user.require_scope(TokenScope::Account)?;
+    let id = path.into_inner();
+
+    let deleted = delete!(&db, UserSession)
+        .condition(and!(
+            UserSession::F.id.equals(&id),
+            UserSession::F.user.equals(&user.username)
+        ))
+        .await?;
+    if deleted == 0 {
+        return Err(Errors::SessionNotFound);
+    }
+
+    Ok(Json(RevokeSessionResponse { success: true }))
+}
+
+/// Revoke every session except the current one
+pub(crate) async fn revoke_all_sessions(
+    db: Data<Database>,
+    user: AuthenticatedUser,
+) -> frontend::Result<Json<RevokeSessionResponse>> {
+    user.require_scope(TokenScope::Account)?;
+
+    delete_user_sessions(&db, &user.username, current_session(&user)).await?;
+
+    Ok(Json(RevokeSessionResponse { success: true }))
+}
@@ -0,0 +1,155 @@
+use actix_toolbox::tb_middleware::Session;
+use actix_web::web::{Data, Json};
+use rorm::{delete, insert, query, update, Database, ForeignModel, Model};
+use serde::{Deserialize, Serialize};
+
+use crate::handler::frontend;
+use crate::handler::frontend::Errors;
+use crate::helper::{totp, AuthenticatedUser, PasswordHashing, TokenScope};
+use crate::models::db::{RecoveryCode, RecoveryCodeInsert, User};
+
+/// Session key of the secret which still has to be confirmed
+const PENDING_SECRET: &str = "totp_pending_secret";
+
+#[derive(Serialize)]
+pub(crate) struct TotpEnrollResponse {
+    success: bool,
+    secret: String,
+    uri: String,
+}
+
+/// Start the enrollment by generating a new secret
+///
+/// The secret is only kept in the session until it is confirmed
+/// with [`totp_confirm`].
+pub(crate) async fn totp_enroll(
+    db: Data<Database>,
+    session: Session,
+    user: AuthenticatedUser,
+) -> frontend::Result<Json<TotpEnrollResponse>> {
+    user.require_scope(TokenScope::Account)?;
+    let username = user.username;
+
+    let db_user = query!(&db, User)
+        .condition(User::F.username.equals(&username))
+        .optional()
+        .await?
+        .ok_or(Errors::Unauthenticated)?;
+    if db_user.totp_secret.is_some() {
+        return Err(Errors::TotpAlreadyEnabled);
+    }
+
+    let secret = totp::generate_secret();
+    session.insert(PENDING_SECRET, &secret)?;
+
+    Ok(Json(TotpEnrollResponse {
+        success: true,
+        uri: totp::otpauth_uri(&secret, &username),
+        secret,
+    }))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct TotpConfirmRequest {
+    code: String,
+}
+
+#[derive(Serialize)]
+pub(crate) struct TotpConfirmResponse {
+    success: bool,
+    recovery_codes: Vec<String>,
+}
+
+/// Finish the enrollment by checking a code generated from the pending secret
+///
+/// The recovery codes are only returned once, the database only stores their hashes.
+pub(crate) async fn totp_confirm(
+    db: Data<Database>,
+    hashing: Data<PasswordHashing>,
+    session: Session,
+    user: AuthenticatedUser,
+    req: Json<TotpConfirmRequest>,
+) -> frontend::Result<Json<TotpConfirmResponse>> {
+    user.require_scope(TokenScope::Account)?;
+    let username = user.username;
+    let secret: String = session
+        .get(PENDING_SECRET)?
+        .ok_or(Errors::TotpNotEnrolled)?;
+
+    let counter = totp::verify_code(&secret, &req.code, 0).ok_or(Errors::SecondFactorInvalid)?;
+
+    let recovery_codes = totp::generate_recovery_codes();
+    let mut inserts = Vec::with_capacity(recovery_codes.len());
+    for code in &recovery_codes {
+        inserts.push(RecoveryCodeInsert {
+            user: ForeignModel::Key(username.clone()),
+            code_hash: hashing.hash(code)?,
+        });
+    }
+
+    update!(&db, User)
+        .set(User::F.totp_secret, Some(secret.as_str()))
+        // The confirmation code must not be usable for a login
+        .set(
+            User::F.totp_last_counter,
+            i64::try_from(counter).unwrap_or(i64::MAX),
+        )
+        .condition(User::F.username.equals(&username))
+        .exec()
+        .await?;
+    delete!(&db, RecoveryCode)
+        .condition(RecoveryCode::F.user.equals(&username))
+        .await?;
+    insert!(&db, RecoveryCodeInsert).bulk(&inserts).await?;
+
+    session.remove(PENDING_SECRET);
+
+    Ok(Json(TotpConfirmResponse {
+        success: true,
+        recovery_codes,
+    }))
+}
+
+#[derive(Deserialize)]
+pub(crate) struct TotpDisableRequest {
+    password: String,
+}
+
+#[derive(Serialize)]
+pub(crate) struct TotpDisableResponse {
+    success: bool,
+}
+
+pub(crate) async fn totp_disable(
+    db: Data<Database>,
+    hashing: Data<PasswordHashing>,
+    user: AuthenticatedUser,
+    req: Json<TotpDisableRequest>,
+) -> frontend::Result<Json<TotpDisableResponse>> {
+    user.require_scope(TokenScope::Account)?;
+    let username = user.username;
+
+    let db_user = query!(&db, User)
+        .condition(User::F.username.equals(&username))
+        .optional()
+        .await?
+        .ok_or(Errors::Unauthenticated)?;
+    if db_user.totp_secret.is_none() {
+        return Err(Errors::TotpNotEnrolled);
+    }
+    if !hashing.verify(&req.password, &db_user.password_hash)? {
+        return Err(Errors::WrongPassword);
+    }
+
+    update!(&db, User)
+        .set(User::F.totp_secret, None::<&str>)
+        .set(User::F.totp_last_counter, 0)
+        .condition(User::F.username.equals(&username))
+        .exec()
+        .await?;
+    delete!(&db, RecoveryCode)
+        .condition(RecoveryCode::F.user.equals(&username))
+        .await?;
+
+    Ok(Json(TotpDisableResponse { success: true }))
+}
@@ -1,2 +1,3 @@
+pub(crate) mod admin;
 pub(crate) mod frontend;
 pub(crate) mod world;
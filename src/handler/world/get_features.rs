@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use actix_web::web::{Data, Json, Query};
+use rustymon_world::geometry::Point;
+use serde::{Deserialize, Serialize};
+
+use super::Errors;
+use crate::models::config::WorldConfig;
+use crate::world::{self, Coord, FeatureKind, MapFeature, OSMTags, SharedWorldIndex};
+
+#[derive(Deserialize)]
+pub(crate) struct FeaturesRequest {
+    /// Comma separated `min_lng,min_lat,max_lng,max_lat`
+    bbox: String,
+}
+
+impl FeaturesRequest {
+    /// Parse and project the bounding box
+    ///
+    /// Returns the projected minimum and maximum corner.
+    fn project(&self) -> Option<(Point, Point)> {
+        let values = self
+            .bbox
+            .split(',')
+            .map(|value| value.trim().parse::<f64>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        let &[min_lng, min_lat, max_lng, max_lat] = values.as_slice() else {
+            return None;
+        };
+        if min_lng > max_lng || min_lat > max_lat {
+            return None;
+        }
+
+        // The projection's y axis points south, so the northern edge becomes the minimum
+        let min = Coord {
+            lat: max_lat,
+            lng: min_lng,
+        }
+        .project()?;
+        let max = Coord {
+            lat: min_lat,
+            lng: max_lng,
+        }
+        .project()?;
+        Some((min, max))
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type")]
+pub(crate) enum Geometry {
+    Polygon { coordinates: Vec<Vec<[f64; 2]>> },
+    LineString { coordinates: Vec<[f64; 2]> },
+    Point { coordinates: [f64; 2] },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename = "Feature")]
+pub(crate) struct Feature {
+    geometry: Geometry,
+    properties: HashMap<&'static str, Vec<&'static str>>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename = "FeatureCollection")]
+pub(crate) struct FeatureCollection {
+    features: Vec<Feature>,
+    /// The box contained more features than the configured limit
+    truncated: bool,
+}
+
+fn to_geojson(feature: &MapFeature, tags: &OSMTags) -> super::Result<Feature> {
+    let mut coordinates = feature.points.iter().map(|&point| {
+        let point = world::unproject(point);
+        [point.x, point.y]
+    });
+    let geometry = match feature.kind {
+        FeatureKind::Area => {
+            let mut ring: Vec<_> = coordinates.collect();
+            // GeoJSON requires closed rings
+            if let (Some(&first), Some(&last)) = (ring.first(), ring.last()) {
+                if first != last {
+                    ring.push(first);
+                }
+            }
+            Geometry::Polygon {
+                coordinates: vec![ring],
+            }
+        }
+        FeatureKind::Way => Geometry::LineString {
+            coordinates: coordinates.collect(),
+        },
+        FeatureKind::Node => Geometry::Point {
+            coordinates: coordinates.next().ok_or(Errors::CorruptedFeature)?,
+        },
+    };
+
+    Ok(Feature {
+        geometry,
+        properties: tags
+            .lookup(feature.features.iter().copied())
+            .ok_or(Errors::CorruptedFeature)?,
+    })
+}
+
+pub(crate) async fn get_features(
+    index: Data<SharedWorldIndex>,
+    config: Data<WorldConfig>,
+    tags: Data<OSMTags>,
+    req: Query<FeaturesRequest>,
+) -> super::Result<Json<FeatureCollection>> {
+    let (min, max) = req.project().ok_or(Errors::InvalidBoundingBox)?;
+
+    let index = index.get();
+    let mut found = index.features_in(min, max);
+    let features = found
+        .by_ref()
+        .take(config.max_features)
+        .map(|feature| to_geojson(feature, &tags))
+        .collect::<super::Result<_>>()?;
+    let truncated = found.next().is_some();
+
+    Ok(Json(FeatureCollection {
+        features,
+        truncated,
+    }))
+}
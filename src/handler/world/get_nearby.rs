@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use actix_web::web::{Data, Json, Query};
+use serde::{Deserialize, Serialize};
+
+use super::Errors;
+use crate::models::config::WorldConfig;
+use crate::world::{self, Coord, FeatureKind, OSMTags, SharedWorldIndex};
+
+/// Number of features returned if the request doesn't specify a limit
+const DEFAULT_LIMIT: usize = 10;
+
+#[derive(Deserialize)]
+pub(crate) struct NearbyRequest {
+    lat: f64,
+    lng: f64,
+    /// Search radius in metres
+    radius: f64,
+    /// Comma separated list of `key` or `key:value` filters
+    tags: Option<String>,
+    /// Maximum number of features to return
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct NearbyFeature {
+    id: i64,
+    kind: FeatureKind,
+    /// Distance from the requested point in metres
+    distance: f64,
+    tags: HashMap<&'static str, Vec<&'static str>>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct NearbyResponse {
+    features: Vec<NearbyFeature>,
+}
+
+/// Parse the tag filter into key and value indices
+fn parse_filter(tags: &OSMTags, filter: &str) -> super::Result<Vec<(u32, Option<u32>)>> {
+    filter
+        .split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| {
+            let (key, value) = match tag.split_once(':') {
+                Some((key, value)) => (key, Some(value)),
+                None => (tag, None),
+            };
+            tags.resolve(key, value).ok_or(Errors::UnknownTag)
+        })
+        .collect()
+}
+
+pub(crate) async fn get_nearby(
+    index: Data<SharedWorldIndex>,
+    config: Data<WorldConfig>,
+    tags: Data<OSMTags>,
+    req: Query<NearbyRequest>,
+) -> super::Result<Json<NearbyResponse>> {
+    let coord = Coord {
+        lat: req.lat,
+        lng: req.lng,
+    };
+    let point = coord.project().ok_or(Errors::InvalidCoordinates)?;
+    let index = index.get();
+    if !index.covers(point) {
+        return Err(Errors::OutsideWorld);
+    }
+    if !(f64::MIN_POSITIVE..=config.max_nearby_radius).contains(&req.radius) {
+        return Err(Errors::InvalidRadius);
+    }
+    let filter = match &req.tags {
+        Some(filter) => parse_filter(&tags, filter)?,
+        None => Vec::new(),
+    };
+    let limit = req
+        .limit
+        .unwrap_or(DEFAULT_LIMIT)
+        .min(config.max_nearby_results);
+
+    let metres_per_unit = world::metres_per_unit(coord.lat);
+    let features = index
+        .nearest(point, req.radius / metres_per_unit, limit, &filter)
+        .into_iter()
+        .map(|(distance, feature)| {
+            Ok(NearbyFeature {
+                id: feature.id,
+                kind: feature.kind,
+                distance: distance * metres_per_unit,
+                tags: tags
+                    .lookup(feature.features.iter().copied())
+                    .ok_or(Errors::CorruptedFeature)?,
+            })
+        })
+        .collect::<super::Result<_>>()?;
+
+    Ok(Json(NearbyResponse { features }))
+}
@@ -1,17 +1,22 @@
 use std::collections::HashMap;
 
 use actix_web::web::{Data, Json, Query};
-use rorm::Database;
 
-use crate::world::{self, Coord, OSMTags};
+use super::Errors;
+use crate::world::{self, Coord, DistanceThresholds, OSMTags, SharedWorldIndex};
 
 pub async fn get_osm_tags(
-    db: Data<Database>,
+    index: Data<SharedWorldIndex>,
+    thresholds: Data<DistanceThresholds>,
     tags: Data<OSMTags>,
     coord: Query<Coord>,
-) -> Json<HashMap<&'static str, Vec<&'static str>>> {
-    Json(
-        tags.lookup(world::get_osm_tags(&db, &coord).await.unwrap().into_iter())
-            .unwrap(),
-    )
+) -> super::Result<Json<HashMap<&'static str, Vec<&'static str>>>> {
+    let point = coord.project().ok_or(Errors::InvalidCoordinates)?;
+    let found =
+        world::get_osm_tags(&index.get(), &thresholds, point).ok_or(Errors::OutsideWorld)?;
+
+    Ok(Json(
+        tags.lookup(found.into_iter())
+            .ok_or(Errors::CorruptedFeature)?,
+    ))
 }
@@ -0,0 +1,186 @@
+use std::fmt::{Display, Formatter};
+use std::future::{ready, Ready};
+
+use actix_web::body::BoxBody;
+use actix_web::dev::Payload;
+use actix_web::http::StatusCode;
+use actix_web::web::Data;
+use actix_web::{FromRequest, HttpRequest, HttpResponse, ResponseError};
+use log::{error, warn};
+use rorm::Database;
+use rstar::{Envelope, RTreeObject, AABB};
+
+use super::Errors;
+use crate::models::config::WorldConfig;
+use crate::world::mvt::{self, TileEncoder};
+use crate::world::pyramid::MIN_ZOOM;
+use crate::world::{
+    tiles, LoadError, MapFeature, OSMTags, SharedWorldIndex, TileAddress, TileCache, ZOOM,
+};
+
+/// Media type of Mapbox vector tiles
+const MVT_CONTENT_TYPE: &str = "application/vnd.mapbox-vector-tile";
+
+/// Header set on tiles which contained more than the configured number of features
+const TRUNCATED_HEADER: &str = "X-Tile-Truncated";
+
+/// Error of the tile route
+///
+/// Map clients only look at the status of a tile response,
+/// so unlike the other world routes the errors are reported by their status code.
+#[derive(Debug)]
+pub(crate) struct TileError(Errors);
+
+impl Display for TileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl ResponseError for TileError {
+    fn status_code(&self) -> StatusCode {
+        match self.0 {
+            Errors::InvalidTile => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<BoxBody> {
+        match &self.0 {
+            Errors::DatabaseError(err) => error!("Database error: {err}"),
+            Errors::CorruptedFeature => {
+                error!("Encountered a feature index which is unknown to the tags lookup")
+            }
+            Errors::CorruptedGeometry(err) => error!("{err}"),
+            _ => {}
+        }
+        HttpResponse::build(self.status_code()).body(self.to_string())
+    }
+}
+
+impl From<Errors> for TileError {
+    fn from(value: Errors) -> Self {
+        TileError(value)
+    }
+}
+
+impl From<LoadError> for TileError {
+    fn from(value: LoadError) -> Self {
+        TileError(value.into())
+    }
+}
+
+impl From<rorm::Error> for TileError {
+    fn from(value: rorm::Error) -> Self {
+        TileError(value.into())
+    }
+}
+
+/// Address of an existing tile taken from the path
+///
+/// Addresses which can't be parsed or are out of range are rejected with a [`TileError`].
+pub(crate) struct ValidTile(TileAddress);
+
+impl FromRequest for ValidTile {
+    type Error = TileError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            req.match_info()
+                .load::<TileAddress>()
+                .ok()
+                .filter(TileAddress::is_valid)
+                .map(ValidTile)
+                .ok_or(TileError(Errors::InvalidTile)),
+        )
+    }
+}
+
+fn encode(encoder: &mut TileEncoder, feature: &MapFeature, tags: &OSMTags) -> super::Result<()> {
+    let properties = tags
+        .lookup(feature.features.iter().copied())
+        .ok_or(Errors::CorruptedFeature)?;
+    encoder.add(feature.kind, &feature.points, &properties);
+    Ok(())
+}
+
+/// Serve a Mapbox vector tile
+///
+/// Tiles without any features are answered with an empty `204 No Content`.
+pub(crate) async fn get_tile(
+    ValidTile(address): ValidTile,
+    db: Data<Database>,
+    cache: Data<TileCache>,
+    index: Data<SharedWorldIndex>,
+    config: Data<WorldConfig>,
+    tags: Data<OSMTags>,
+) -> Result<HttpResponse, TileError> {
+    let mut encoder = TileEncoder::new(address);
+    let (min, max) = mvt::buffered_bounds(address);
+    let mut truncated = false;
+    if address.z >= ZOOM {
+        // Full detail and deeper zoom levels are cut from the world index
+        let index = index.get();
+        let mut found = index.features_in(min, max);
+        for feature in found.by_ref().take(config.max_features) {
+            encode(&mut encoder, feature, &tags)?;
+        }
+        truncated = found.next().is_some();
+    } else if address.z >= MIN_ZOOM {
+        // Below the pyramid there is no data
+        let envelope = AABB::from_corners([min.x, min.y], [max.x, max.y]);
+        let mut count = 0;
+        'tiles: for tile in tiles::tiles_in_box(&db, address.z, min, max).await? {
+            for feature in cache.load(&db, tile.id).await?.iter() {
+                if !feature.envelope().intersects(&envelope) {
+                    continue;
+                }
+                if count >= config.max_features {
+                    truncated = true;
+                    break 'tiles;
+                }
+                count += 1;
+                encode(&mut encoder, feature, &tags)?;
+            }
+        }
+    }
+
+    let tile = encoder.finish();
+    if tile.is_empty() {
+        return Ok(HttpResponse::NoContent().finish());
+    }
+
+    let mut response = HttpResponse::Ok();
+    response.content_type(MVT_CONTENT_TYPE);
+    if truncated {
+        warn!(
+            "Tile {}/{}/{} has more than {} features, the rest has been left out",
+            address.z, address.x, address.y, config.max_features
+        );
+        response.insert_header((TRUNCATED_HEADER, "true"));
+    }
+    Ok(response.body(tile))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::web::get;
+    use actix_web::{test, App};
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn bad_addresses_are_rejected() {
+        // The address is checked before any of the shared state is needed
+        let app =
+            test::init_service(App::new().route("/tiles/{z}/{x}/{y}.mvt", get().to(get_tile)))
+                .await;
+
+        for uri in ["/tiles/2/4/0.mvt", "/tiles/23/0/0.mvt", "/tiles/a/0/0.mvt"] {
+            let response =
+                test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{uri}");
+        }
+    }
+}
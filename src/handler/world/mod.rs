@@ -1,3 +1,151 @@
-pub(crate) mod get_osm_tags;
+use std::fmt::{Debug, Display, Formatter};
+
+use actix_web::body::BoxBody;
+use actix_web::HttpResponse;
+use log::error;
+use serde::Serialize;
+use serde_repr::Serialize_repr;
+
+use crate::world::LoadError;
 
+pub(crate) use get_features::get_features;
+pub(crate) use get_nearby::get_nearby;
 pub(crate) use get_osm_tags::get_osm_tags;
+pub(crate) use get_tile::get_tile;
+
+pub(crate) mod get_features;
+pub(crate) mod get_nearby;
+pub(crate) mod get_osm_tags;
+pub(crate) mod get_tile;
+
+#[derive(Serialize_repr)]
+#[repr(u16)]
+pub(crate) enum ErrorStatusCode {
+    InvalidCoordinates = 200,
+    OutsideWorld = 201,
+    InvalidBoundingBox = 202,
+    InvalidTile = 203,
+    UnknownTag = 204,
+    InvalidRadius = 205,
+    DatabaseError = 500,
+    CorruptedFeature = 503,
+    CorruptedGeometry = 504,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ErrorResponse {
+    success: bool,
+    status_code: ErrorStatusCode,
+    message: String,
+}
+
+impl ErrorResponse {
+    fn new(status_code: ErrorStatusCode, message: String) -> Self {
+        Self {
+            success: false,
+            status_code,
+            message,
+        }
+    }
+}
+
+pub(crate) type Result<T> = std::result::Result<T, Errors>;
+
+#[derive(Debug)]
+pub(crate) enum Errors {
+    InvalidCoordinates,
+    OutsideWorld,
+    InvalidBoundingBox,
+    InvalidTile,
+    UnknownTag,
+    InvalidRadius,
+    DatabaseError(rorm::Error),
+    CorruptedFeature,
+    CorruptedGeometry(String),
+}
+
+impl Display for Errors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Errors::InvalidCoordinates => write!(f, "Invalid coordinates"),
+            Errors::OutsideWorld => write!(f, "The coordinates are outside of the world"),
+            Errors::InvalidBoundingBox => write!(f, "Invalid bounding box"),
+            Errors::InvalidTile => write!(f, "The tile does not exist"),
+            Errors::UnknownTag => write!(f, "Unknown tag in the filter"),
+            Errors::InvalidRadius => write!(f, "Invalid radius"),
+            Errors::DatabaseError(_) => write!(f, "Database error occurred"),
+            Errors::CorruptedFeature => write!(f, "Corrupted feature in the world data"),
+            Errors::CorruptedGeometry(_) => write!(f, "Corrupted geometry in the world data"),
+        }
+    }
+}
+
+impl actix_web::ResponseError for Errors {
+    fn error_response(&self) -> HttpResponse<BoxBody> {
+        match self {
+            Errors::InvalidCoordinates => HttpResponse::Ok().json(ErrorResponse::new(
+                ErrorStatusCode::InvalidCoordinates,
+                self.to_string(),
+            )),
+            Errors::OutsideWorld => HttpResponse::Ok().json(ErrorResponse::new(
+                ErrorStatusCode::OutsideWorld,
+                self.to_string(),
+            )),
+            Errors::InvalidBoundingBox => HttpResponse::Ok().json(ErrorResponse::new(
+                ErrorStatusCode::InvalidBoundingBox,
+                self.to_string(),
+            )),
+            Errors::InvalidTile => HttpResponse::Ok().json(ErrorResponse::new(
+                ErrorStatusCode::InvalidTile,
+                self.to_string(),
+            )),
+            Errors::UnknownTag => HttpResponse::Ok().json(ErrorResponse::new(
+                ErrorStatusCode::UnknownTag,
+                self.to_string(),
+            )),
+            Errors::InvalidRadius => HttpResponse::Ok().json(ErrorResponse::new(
+                ErrorStatusCode::InvalidRadius,
+                self.to_string(),
+            )),
+            Errors::DatabaseError(err) => {
+                error!("Database error: {err}");
+
+                HttpResponse::Ok().json(ErrorResponse::new(
+                    ErrorStatusCode::DatabaseError,
+                    self.to_string(),
+                ))
+            }
+            Errors::CorruptedFeature => {
+                error!("Encountered a feature index which is unknown to the tags lookup");
+
+                HttpResponse::Ok().json(ErrorResponse::new(
+                    ErrorStatusCode::CorruptedFeature,
+                    self.to_string(),
+                ))
+            }
+            Errors::CorruptedGeometry(err) => {
+                error!("{err}");
+
+                HttpResponse::Ok().json(ErrorResponse::new(
+                    ErrorStatusCode::CorruptedGeometry,
+                    self.to_string(),
+                ))
+            }
+        }
+    }
+}
+
+impl From<LoadError> for Errors {
+    fn from(value: LoadError) -> Self {
+        match value {
+            LoadError::Database(err) => Errors::DatabaseError(err),
+            LoadError::Corrupted { .. } => Errors::CorruptedGeometry(value.to_string()),
+        }
+    }
+}
+
+impl From<rorm::Error> for Errors {
+    fn from(value: rorm::Error) -> Self {
+        Errors::DatabaseError(value)
+    }
+}
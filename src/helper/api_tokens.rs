@@ -0,0 +1,28 @@
+use data_encoding::{BASE64URL_NOPAD, HEXLOWER};
+use rand::{thread_rng, RngCore};
+use sha2::{Digest, Sha256};
+
+/// Prefix of every api token, makes them easy to recognize e.g. by secret scanners
+const TOKEN_PREFIX: &str = "rmy_";
+const TOKEN_LENGTH: usize = 32;
+
+/// Generate a new api token
+///
+/// Returns the token, which is only shown to the user once, and its hash
+/// which is stored in the database.
+pub(crate) fn generate_token() -> (String, String) {
+    let mut bytes = [0; TOKEN_LENGTH];
+    thread_rng().fill_bytes(&mut bytes);
+
+    let token = format!("{TOKEN_PREFIX}{}", BASE64URL_NOPAD.encode(&bytes));
+    let hash = hash_token(&token);
+    (token, hash)
+}
+
+/// Hash an api token for storage or lookup
+///
+/// Tokens have enough entropy to not require a slow password hash,
+/// which allows looking them up by their hash.
+pub(crate) fn hash_token(token: &str) -> String {
+    HEXLOWER.encode(&Sha256::digest(token.as_bytes()))
+}
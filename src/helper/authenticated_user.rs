@@ -0,0 +1,109 @@
+use std::future::{ready, Ready};
+
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use serde::{Deserialize, Serialize};
+
+use crate::handler::frontend::Errors;
+
+/// Permission which can be granted to an api token
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TokenScope {
+    /// Read the profile and play the game
+    Profile,
+    /// Manage the account itself, e.g. its password or api tokens
+    Account,
+    /// Use endpoints which require an elevated [`Role`](crate::models::db::Role)
+    Admin,
+}
+
+impl TokenScope {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            TokenScope::Profile => "profile",
+            TokenScope::Account => "account",
+            TokenScope::Admin => "admin",
+        }
+    }
+
+    fn from_str(scope: &str) -> Option<Self> {
+        match scope {
+            "profile" => Some(TokenScope::Profile),
+            "account" => Some(TokenScope::Account),
+            "admin" => Some(TokenScope::Admin),
+            _ => None,
+        }
+    }
+
+    /// Parse the space separated representation stored in the database
+    ///
+    /// Unknown scopes are ignored.
+    pub(crate) fn parse_list(scopes: &str) -> Vec<Self> {
+        scopes
+            .split_whitespace()
+            .filter_map(Self::from_str)
+            .collect()
+    }
+
+    /// Build the space separated representation stored in the database
+    pub(crate) fn format_list(scopes: &[Self]) -> String {
+        scopes
+            .iter()
+            .map(|scope| scope.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// How the current request was authenticated
+#[derive(Clone, Debug)]
+pub(crate) enum AuthenticationMethod {
+    /// Session cookie of a regular login, which may do everything
+    Session { id: String },
+    /// Api token which is restricted to its scopes
+    Token { id: i64, scopes: Vec<TokenScope> },
+}
+
+/// The user of the current request
+///
+/// It is resolved by [`AuthenticationRequired`](crate::helper::AuthenticationRequired)
+/// from either the session or an api token and can be used as extractor by handlers.
+#[derive(Clone, Debug)]
+pub(crate) struct AuthenticatedUser {
+    pub(crate) username: String,
+    pub(crate) method: AuthenticationMethod,
+}
+
+impl AuthenticatedUser {
+    /// Check whether the request may use an endpoint requiring `scope`
+    pub(crate) fn has_scope(&self, scope: TokenScope) -> bool {
+        match &self.method {
+            AuthenticationMethod::Session { .. } => true,
+            AuthenticationMethod::Token { scopes, .. } => scopes.contains(&scope),
+        }
+    }
+
+    /// Like [`has_scope`](Self::has_scope), but returns an error to use with `?`
+    pub(crate) fn require_scope(&self, scope: TokenScope) -> Result<(), Errors> {
+        if self.has_scope(scope) {
+            Ok(())
+        } else {
+            Err(Errors::MissingScope)
+        }
+    }
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = Errors;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<AuthenticatedUser>()
+                .cloned()
+                .ok_or(Errors::Unauthenticated),
+        )
+    }
+}
@@ -1,17 +1,35 @@
 use std::future::{ready, Ready};
+use std::rc::Rc;
 
 use actix_toolbox::tb_middleware::actix_session::SessionExt;
 use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::web::Data;
+use actix_web::HttpMessage;
+use chrono::{Duration, Utc};
 use futures::future::LocalBoxFuture;
 use log::debug;
+use rorm::{and, query, update, Database, ForeignModel, Model};
 
 use crate::handler::frontend::Errors;
+use crate::helper::api_tokens::hash_token;
+use crate::helper::sessions::SESSION_ID;
+use crate::helper::{AuthenticatedUser, AuthenticationMethod, TokenScope};
+use crate::models::db::{ApiToken, UserSession};
 
+/// Minimum time between two updates of an api token's `last_used` or a session's `last_seen`
+const LAST_USED_PRECISION: i64 = 60;
+
+/// Middleware which rejects unauthenticated requests
+///
+/// Requests are authenticated either by the session cookie or by an
+/// `Authorization: Bearer` api token. The resolved [`AuthenticatedUser`]
+/// is stored in the request's extensions for the handlers.
 pub(crate) struct AuthenticationRequired;
 
 impl<S, B> Transform<S, ServiceRequest> for AuthenticationRequired
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -22,17 +40,19 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(AuthenticationRequiredMiddleware { service }))
+        ready(Ok(AuthenticationRequiredMiddleware {
+            service: Rc::new(service),
+        }))
     }
 }
 
 pub(crate) struct AuthenticationRequiredMiddleware<S> {
-    service: S,
+    service: Rc<S>,
 }
 
 impl<S, B> Service<ServiceRequest> for AuthenticationRequiredMiddleware<S>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -43,32 +63,111 @@ where
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        let session = req.get_session();
-
-        let logged_in = match session.get("logged_in") {
-            Ok(logged_in_maybe) => match logged_in_maybe {
-                Some(logged_in) => match logged_in {
-                    true => Ok(true),
-                    false => Ok(false),
-                },
-                None => Ok(false),
-            },
-            Err(err) => Err(err),
-        };
-
-        let next = self.service.call(req);
-        Box::pin(async {
-            match logged_in {
-                Ok(v) => {
-                    if v {
-                        next.await
-                    } else {
-                        debug!("Session is unauthenticated");
-                        Err(actix_web::Error::from(Errors::Unauthenticated))
-                    }
-                }
-                Err(err) => Err(err.into()),
-            }
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let bearer = req
+                .headers()
+                .get(AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .map(|token| token.trim().to_string());
+
+            let db = req
+                .app_data::<Data<Database>>()
+                .expect("Database should be registered as app data")
+                .clone();
+            let user = match bearer {
+                Some(token) => authenticate_token(&db, &token).await?,
+                None => authenticate_session(&db, &req).await?,
+            };
+
+            req.extensions_mut().insert(user);
+            service.call(req).await
         })
     }
 }
+
+async fn authenticate_session(
+    db: &Database,
+    req: &ServiceRequest,
+) -> Result<AuthenticatedUser, Errors> {
+    let session = req.get_session();
+
+    let logged_in: bool = session.get("logged_in")?.unwrap_or(false);
+    if !logged_in {
+        debug!("Session is unauthenticated");
+        return Err(Errors::Unauthenticated);
+    }
+
+    let username: String = session.get("user")?.ok_or(Errors::Unauthenticated)?;
+    let id: Option<String> = session.get(SESSION_ID)?;
+
+    let user_session = match id {
+        Some(id) => {
+            query!(db, UserSession)
+                .condition(and!(
+                    UserSession::F.id.equals(&id),
+                    UserSession::F.user.equals(&username)
+                ))
+                .optional()
+                .await?
+        }
+        None => None,
+    };
+    let Some(user_session) = user_session else {
+        debug!("Session has been revoked");
+        session.purge();
+        return Err(Errors::Unauthenticated);
+    };
+
+    let now = Utc::now().naive_utc();
+    if now - user_session.last_seen > Duration::seconds(LAST_USED_PRECISION) {
+        update!(db, UserSession)
+            .set(UserSession::F.last_seen, now)
+            .condition(UserSession::F.id.equals(&user_session.id))
+            .exec()
+            .await?;
+    }
+
+    Ok(AuthenticatedUser {
+        username,
+        method: AuthenticationMethod::Session {
+            id: user_session.id,
+        },
+    })
+}
+
+async fn authenticate_token(db: &Database, token: &str) -> Result<AuthenticatedUser, Errors> {
+    let Some(token) = query!(db, ApiToken)
+        .condition(ApiToken::F.token_hash.equals(&hash_token(token)))
+        .optional()
+        .await?
+    else {
+        debug!("Unknown api token");
+        return Err(Errors::Unauthenticated);
+    };
+
+    let now = Utc::now().naive_utc();
+    if token.last_used.map_or(true, |last_used| {
+        now - last_used > Duration::seconds(LAST_USED_PRECISION)
+    }) {
+        update!(db, ApiToken)
+            .set(ApiToken::F.last_used, Some(now))
+            .condition(ApiToken::F.id.equals(token.id))
+            .exec()
+            .await?;
+    }
+
+    let username = match token.user {
+        ForeignModel::Key(username) => username,
+        ForeignModel::Instance(user) => user.username,
+    };
+    Ok(AuthenticatedUser {
+        username,
+        method: AuthenticationMethod::Token {
+            id: token.id,
+            scopes: TokenScope::parse_list(&token.scopes),
+        },
+    })
+}
@@ -0,0 +1,339 @@
+use std::collections::{BTreeSet, HashMap};
+use std::hash::Hash;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::http::header;
+use actix_web::HttpRequest;
+
+use crate::models::config::LoginProtection;
+
+/// Upper bound of a single lockout regardless of the configuration
+///
+/// Keeps the end of the lockout representable as an [`Instant`].
+const MAX_LOCKOUT: u64 = 365 * 24 * 60 * 60;
+
+struct FailedAttempts {
+    count: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl FailedAttempts {
+    /// Point in time after which the entry neither counts failures nor is locked
+    fn expires(&self, reset_after: Duration) -> Instant {
+        let reset = self.last_failure + reset_after;
+        self.locked_until.map_or(reset, |until| until.max(reset))
+    }
+}
+
+/// Failed attempts of one kind of key, i.e. usernames or client ips
+///
+/// Once `max_tracked` keys are tracked, the entry which expires first is forgotten.
+/// These are the entries which failed longest ago and aren't locked for longer.
+struct Tracker<K> {
+    attempts: HashMap<K, FailedAttempts>,
+    /// The tracked keys ordered by the expiry of their entries
+    expiry: BTreeSet<(Instant, K)>,
+    free_attempts: u32,
+}
+
+impl<K: Clone + Ord + Hash> Tracker<K> {
+    fn new(free_attempts: u32) -> Self {
+        Self {
+            attempts: HashMap::new(),
+            expiry: BTreeSet::new(),
+            free_attempts,
+        }
+    }
+
+    /// Get the remaining lockout of a key
+    fn locked(&self, key: &K, now: Instant) -> Option<Duration> {
+        let until = self.attempts.get(key)?.locked_until?;
+        (until > now).then(|| until - now)
+    }
+
+    /// Remove the entries which have expired
+    fn prune(&mut self, now: Instant) {
+        while let Some((expires, _)) = self.expiry.first() {
+            if *expires > now {
+                break;
+            }
+            if let Some((_, key)) = self.expiry.pop_first() {
+                self.attempts.remove(&key);
+            }
+        }
+    }
+
+    fn failed(&mut self, key: K, now: Instant, config: &LoginProtection) {
+        let reset_after = Duration::from_secs(config.reset_after.min(MAX_LOCKOUT));
+        self.prune(now);
+
+        match self.attempts.get(&key) {
+            Some(attempts) => {
+                self.expiry
+                    .remove(&(attempts.expires(reset_after), key.clone()));
+            }
+            None if self.attempts.len() >= config.max_tracked => {
+                if let Some((_, evicted)) = self.expiry.pop_first() {
+                    self.attempts.remove(&evicted);
+                }
+            }
+            None => {}
+        }
+
+        let attempts = self.attempts.entry(key.clone()).or_insert(FailedAttempts {
+            count: 0,
+            last_failure: now,
+            locked_until: None,
+        });
+        if now - attempts.last_failure >= reset_after {
+            attempts.count = 0;
+        }
+        attempts.count += 1;
+        attempts.last_failure = now;
+
+        if attempts.count > self.free_attempts {
+            let exponent = (attempts.count - self.free_attempts - 1).min(31);
+            let lockout = config
+                .base_lockout
+                .saturating_mul(1 << exponent)
+                .min(config.max_lockout)
+                .min(MAX_LOCKOUT);
+            attempts.locked_until = Some(now + Duration::from_secs(lockout));
+        }
+        self.expiry.insert((attempts.expires(reset_after), key));
+    }
+
+    fn remove(&mut self, key: &K, config: &LoginProtection) {
+        let reset_after = Duration::from_secs(config.reset_after.min(MAX_LOCKOUT));
+        if let Some(attempts) = self.attempts.remove(key) {
+            self.expiry
+                .remove(&(attempts.expires(reset_after), key.clone()));
+        }
+    }
+}
+
+/// In-memory tracker of failed login attempts
+///
+/// Attempts are counted per username and per client ip independently,
+/// a login is rejected if either of them is locked.
+/// At most `MaxTracked` usernames and as many ips are remembered,
+/// so flooding one kind of key doesn't evict the other.
+/// Once they are exhausted, the entries which failed longest ago are forgotten first.
+pub(crate) struct LoginThrottle {
+    config: LoginProtection,
+    users: Mutex<Tracker<String>>,
+    ips: Mutex<Tracker<IpAddr>>,
+}
+
+impl LoginThrottle {
+    pub(crate) fn new(config: LoginProtection) -> Self {
+        Self {
+            users: Mutex::new(Tracker::new(config.user_attempts)),
+            ips: Mutex::new(Tracker::new(config.ip_attempts)),
+            config,
+        }
+    }
+
+    /// Get the ip of the client which sent a request
+    ///
+    /// Requests from a trusted proxy are attributed to the client ip it forwarded.
+    /// The forwarded addresses are walked from the right, i.e. starting at the proxy
+    /// closest to the server, as every entry left of an untrusted hop may be made up.
+    pub(crate) fn client_ip(&self, request: &HttpRequest) -> Option<IpAddr> {
+        let mut client = request.peer_addr()?.ip();
+        for hop in forwarded_chain(request).iter().rev() {
+            if !self.config.trusted_proxies.contains(&client) {
+                break;
+            }
+            match parse_hop(hop) {
+                Some(ip) => client = ip,
+                // A proxy which doesn't forward a valid address is treated as the client
+                None => break,
+            }
+        }
+        Some(client)
+    }
+
+    /// Check whether a login attempt is allowed right now
+    ///
+    /// Returns the remaining lockout if it isn't.
+    pub(crate) fn check(&self, username: &str, ip: Option<IpAddr>) -> Result<(), Duration> {
+        let now = Instant::now();
+        let user = self
+            .users
+            .lock()
+            .expect("Login throttle mutex got poisoned")
+            .locked(&username.to_string(), now);
+        let ip = ip.and_then(|ip| {
+            self.ips
+                .lock()
+                .expect("Login throttle mutex got poisoned")
+                .locked(&ip, now)
+        });
+
+        match user.max(ip) {
+            Some(remaining) => Err(remaining),
+            None => Ok(()),
+        }
+    }
+
+    /// Record a failed login attempt
+    pub(crate) fn failed(&self, username: &str, ip: Option<IpAddr>) {
+        let now = Instant::now();
+        self.users
+            .lock()
+            .expect("Login throttle mutex got poisoned")
+            .failed(username.to_string(), now, &self.config);
+        if let Some(ip) = ip {
+            self.ips
+                .lock()
+                .expect("Login throttle mutex got poisoned")
+                .failed(ip, now, &self.config);
+        }
+    }
+
+    /// Record a successful login
+    ///
+    /// This only clears the failures of the username, a client ip
+    /// shouldn't be able to reset its counter by logging into its own account.
+    pub(crate) fn succeeded(&self, username: &str) {
+        self.users
+            .lock()
+            .expect("Login throttle mutex got poisoned")
+            .remove(&username.to_string(), &self.config);
+    }
+}
+
+/// Get the addresses in the `Forwarded` or else the `X-Forwarded-For` headers
+///
+/// The entries are ordered from the client to the proxy closest to the server.
+fn forwarded_chain(request: &HttpRequest) -> Vec<String> {
+    let headers = request.headers();
+    let forwarded: Vec<_> = headers
+        .get_all(header::FORWARDED)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (name, value) = pair.trim().split_once('=')?;
+                name.eq_ignore_ascii_case("for")
+                    .then(|| value.trim().trim_matches('"').to_string())
+            })
+        })
+        .collect();
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+
+    headers
+        .get_all(header::X_FORWARDED_FOR)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|hop| hop.trim().to_string())
+        .collect()
+}
+
+/// Parse a forwarded address which may include a port and brackets around ipv6 addresses
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    hop.parse::<IpAddr>()
+        .or_else(|_| hop.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .or_else(|_| {
+            hop.trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse::<IpAddr>()
+        })
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn throttle(trusted_proxies: &[&str]) -> LoginThrottle {
+        LoginThrottle::new(LoginProtection {
+            trusted_proxies: trusted_proxies
+                .iter()
+                .map(|ip| ip.parse().unwrap())
+                .collect(),
+            ..LoginProtection::default()
+        })
+    }
+
+    fn ip(ip: &str) -> Option<IpAddr> {
+        Some(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn spoofed_forwarded_entries_are_ignored() {
+        let throttle = throttle(&["10.0.0.1", "10.0.0.2"]);
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:443".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "6.6.6.6, 203.0.113.7, 10.0.0.2"))
+            .to_http_request();
+
+        assert_eq!(throttle.client_ip(&request), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn forwarded_header_is_preferred() {
+        let throttle = throttle(&["10.0.0.1"]);
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:443".parse().unwrap())
+            .insert_header((
+                "Forwarded",
+                r#"for=6.6.6.6, for="[2001:db8::1]:4711";proto=https"#,
+            ))
+            .insert_header(("X-Forwarded-For", "203.0.113.7"))
+            .to_http_request();
+
+        assert_eq!(throttle.client_ip(&request), ip("2001:db8::1"));
+    }
+
+    #[test]
+    fn untrusted_peers_are_the_client() {
+        let throttle = throttle(&["10.0.0.1"]);
+        let request = TestRequest::default()
+            .peer_addr("198.51.100.1:443".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "203.0.113.7"))
+            .to_http_request();
+
+        assert_eq!(throttle.client_ip(&request), ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn usernames_lock_after_their_free_attempts() {
+        let throttle = throttle(&[]);
+        for _ in 0..throttle.config.user_attempts {
+            assert!(throttle.check("alice", None).is_ok());
+            throttle.failed("alice", None);
+        }
+        throttle.failed("alice", None);
+
+        assert!(throttle.check("alice", None).is_err());
+        throttle.succeeded("alice");
+        assert!(throttle.check("alice", None).is_ok());
+    }
+
+    #[test]
+    fn flooding_usernames_keeps_the_ip_counters() {
+        let throttle = LoginThrottle::new(LoginProtection {
+            ip_attempts: 1,
+            max_tracked: 10,
+            ..LoginProtection::default()
+        });
+        let attacker = ip("203.0.113.7");
+        throttle.failed("alice", attacker);
+        throttle.failed("alice", attacker);
+        for user in 0..100 {
+            throttle.failed(&format!("user{user}"), None);
+        }
+
+        assert_eq!(throttle.users.lock().unwrap().attempts.len(), 10);
+        assert!(throttle.check("bob", attacker).is_err());
+    }
+}
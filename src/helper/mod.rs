@@ -1,3 +1,17 @@
+pub(crate) use authenticated_user::{AuthenticatedUser, AuthenticationMethod, TokenScope};
 pub(crate) use authentication_required::AuthenticationRequired;
+pub(crate) use login_throttle::LoginThrottle;
+pub(crate) use password::PasswordHashing;
+pub(crate) use role_required::RoleRequired;
+pub(crate) use sessions::{delete_user_sessions, replace_password, start_session};
+pub(crate) use validation::{valid_username, DisplayNamePolicy};
 
+pub(crate) mod api_tokens;
+mod authenticated_user;
 mod authentication_required;
+mod login_throttle;
+mod password;
+mod role_required;
+pub(crate) mod sessions;
+pub(crate) mod totp;
+mod validation;
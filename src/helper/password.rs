@@ -0,0 +1,138 @@
+use argon2::password_hash::{Error, SaltString};
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+
+use crate::models::config::Argon2Config;
+
+/// Password hashing with the configured argon2 parameters
+pub(crate) struct PasswordHashing {
+    argon2: Argon2<'static>,
+    params: Params,
+    /// Hash of a random password
+    ///
+    /// It is verified when a user doesn't exist,
+    /// which makes the response time independent of the user's existence.
+    dummy_hash: String,
+}
+
+impl PasswordHashing {
+    pub(crate) fn new(config: &Argon2Config) -> Result<Self, String> {
+        let params = Params::new(
+            config.memory_cost,
+            config.time_cost,
+            config.parallelism,
+            None,
+        )
+        .map_err(|e| format!("Invalid argon2 parameters: {e}"))?;
+
+        let mut hashing = Self {
+            argon2: Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone()),
+            params,
+            dummy_hash: String::new(),
+        };
+
+        let dummy_password: String = thread_rng()
+            .sample_iter(Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect();
+        hashing.dummy_hash = hashing
+            .hash(&dummy_password)
+            .map_err(|e| format!("Could not hash dummy password: {e}"))?;
+
+        Ok(hashing)
+    }
+
+    /// Hash a password with a freshly generated salt
+    pub(crate) fn hash(&self, password: &str) -> Result<String, Error> {
+        let salt = SaltString::generate(&mut thread_rng());
+        Ok(self
+            .argon2
+            .hash_password(password.as_bytes(), &salt)?
+            .to_string())
+    }
+
+    /// Check a password against a stored hash
+    ///
+    /// The hash is verified with the parameters it was created with,
+    /// not the configured ones.
+    /// A mismatching password is reported as `Ok(false)`, only malformed hashes
+    /// and other internal problems are returned as errors.
+    pub(crate) fn verify(&self, password: &str, hash: &str) -> Result<bool, Error> {
+        match self
+            .argon2
+            .verify_password(password.as_bytes(), &PasswordHash::new(hash)?)
+        {
+            Ok(()) => Ok(true),
+            Err(Error::Password) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Verify a password against the dummy hash, the result is meaningless
+    pub(crate) fn verify_dummy(&self, password: &str) -> Result<(), Error> {
+        self.verify(password, &self.dummy_hash).map(|_| ())
+    }
+
+    /// Check whether a hash was created with weaker settings than the configured ones
+    ///
+    /// A hash is only replaced if the configured parameters are at least as strong
+    /// in every dimension, so rehashing never lowers any of its costs.
+    pub(crate) fn needs_rehash(&self, hash: &str) -> bool {
+        let Ok(hash) = PasswordHash::new(hash) else {
+            return false;
+        };
+        if hash.algorithm != Algorithm::Argon2id.ident()
+            || hash.version != Some(Version::V0x13.into())
+        {
+            return true;
+        }
+
+        match Params::try_from(&hash) {
+            Ok(params) => {
+                let costs = [
+                    (params.m_cost(), self.params.m_cost()),
+                    (params.t_cost(), self.params.t_cost()),
+                    (params.p_cost(), self.params.p_cost()),
+                ];
+                costs
+                    .iter()
+                    .all(|(stored, configured)| stored <= configured)
+                    && costs.iter().any(|(stored, configured)| stored < configured)
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hashing(memory_cost: u32, time_cost: u32) -> PasswordHashing {
+        PasswordHashing::new(&Argon2Config {
+            memory_cost,
+            time_cost,
+            parallelism: 1,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn weaker_hashes_are_rehashed() {
+        let hash = hashing(16, 1).hash("password").unwrap();
+
+        assert!(hashing(32, 1).needs_rehash(&hash));
+        assert!(hashing(16, 2).needs_rehash(&hash));
+        assert!(!hashing(16, 1).needs_rehash(&hash));
+    }
+
+    #[test]
+    fn no_cost_is_lowered() {
+        let hash = hashing(32, 1).hash("password").unwrap();
+
+        assert!(!hashing(16, 2).needs_rehash(&hash));
+        assert!(hashing(16, 2).verify("password", &hash).unwrap());
+    }
+}
@@ -0,0 +1,96 @@
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::web::Data;
+use actix_web::HttpMessage;
+use futures::future::LocalBoxFuture;
+use log::debug;
+use rorm::{query, Database, Model};
+
+use crate::handler::frontend::Errors;
+use crate::helper::{AuthenticatedUser, TokenScope};
+use crate::models::db::{Role, User};
+
+/// Middleware which only lets users with at least the given [`Role`] pass
+///
+/// The role is looked up in the database on every request, so changes
+/// take effect immediately. Api tokens additionally need the
+/// [`TokenScope::Admin`] scope.
+///
+/// It expects the [`AuthenticatedUser`] to be resolved already,
+/// so it has to be used together with
+/// [`AuthenticationRequired`](crate::helper::AuthenticationRequired).
+pub(crate) struct RoleRequired(pub(crate) Role);
+
+impl<S, B> Transform<S, ServiceRequest> for RoleRequired
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = RoleRequiredMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RoleRequiredMiddleware {
+            service: Rc::new(service),
+            role: self.0,
+        }))
+    }
+}
+
+pub(crate) struct RoleRequiredMiddleware<S> {
+    service: Rc<S>,
+    role: Role,
+}
+
+impl<S, B> Service<ServiceRequest> for RoleRequiredMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let required = self.role;
+
+        Box::pin(async move {
+            let authenticated = req
+                .extensions()
+                .get::<AuthenticatedUser>()
+                .cloned()
+                .ok_or(Errors::Unauthenticated)?;
+            authenticated.require_scope(TokenScope::Admin)?;
+            let username = authenticated.username;
+
+            let db = req
+                .app_data::<Data<Database>>()
+                .expect("Database should be registered as app data")
+                .clone();
+
+            let user = query!(&db, User)
+                .condition(User::F.username.equals(&username))
+                .optional()
+                .await
+                .map_err(Errors::from)?
+                .ok_or(Errors::Unauthenticated)?;
+
+            if user.role >= required {
+                service.call(req).await
+            } else {
+                debug!("User {username} is missing the role {required:?}");
+                Err(Errors::MissingPrivileges.into())
+            }
+        })
+    }
+}
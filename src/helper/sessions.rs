@@ -0,0 +1,138 @@
+use actix_toolbox::tb_middleware::Session;
+use actix_web::http::header::USER_AGENT;
+use actix_web::HttpRequest;
+use chrono::{Duration, NaiveDateTime, Utc};
+use data_encoding::BASE64URL_NOPAD;
+use rand::{thread_rng, RngCore};
+use rorm::{and, delete, insert, update, Database, ForeignModel, Model};
+
+use crate::handler::frontend::Errors;
+use crate::models::db::{ApiToken, User, UserSession, UserSessionInsert};
+
+/// Session key of the [`UserSession`]'s id
+pub(crate) const SESSION_ID: &str = "session_id";
+
+/// Seconds after which an unused session cookie expires
+pub(crate) const SESSION_TTL: i64 = 60 * 60;
+
+const SESSION_ID_LENGTH: usize = 24;
+const MAX_USER_AGENT_LENGTH: usize = 1024;
+
+/// Log a user into the current session
+///
+/// Besides setting the session's `logged_in` and `user` keys, this creates a
+/// [`UserSession`] which lets the user list and revoke their sessions.
+pub(crate) async fn start_session(
+    db: &Database,
+    session: &Session,
+    request: &HttpRequest,
+    username: &str,
+) -> Result<(), Errors> {
+    let mut bytes = [0; SESSION_ID_LENGTH];
+    thread_rng().fill_bytes(&mut bytes);
+    let id = BASE64URL_NOPAD.encode(&bytes);
+
+    // Logins are rare enough to clean up the sessions whose cookies expired here
+    delete!(db, UserSession)
+        .condition(UserSession::F.last_seen.less_than(expired_before()))
+        .await?;
+
+    let user_agent = request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.chars().take(MAX_USER_AGENT_LENGTH).collect());
+
+    insert!(db, UserSessionInsert)
+        .single(&UserSessionInsert {
+            id: id.clone(),
+            user: ForeignModel::Key(username.to_string()),
+            last_seen: Utc::now().naive_utc(),
+            user_agent,
+            ip: request.peer_addr().map(|addr| addr.ip().to_string()),
+        })
+        .await?;
+
+    session.insert("logged_in", true)?;
+    session.insert("user", username)?;
+    session.insert(SESSION_ID, &id)?;
+
+    Ok(())
+}
+
+/// Sessions last seen before this point in time have expired
+///
+/// The cookie's lifetime only starts over when the session changes,
+/// so a session is never seen later than its cookie was refreshed.
+pub(crate) fn expired_before() -> NaiveDateTime {
+    Utc::now().naive_utc() - Duration::seconds(SESSION_TTL)
+}
+
+/// Revoke every session of a user, except the one with the id `keep`
+pub(crate) async fn delete_user_sessions(
+    db: &Database,
+    username: &str,
+    keep: Option<&str>,
+) -> Result<(), rorm::Error> {
+    match keep {
+        Some(keep) => {
+            delete!(db, UserSession)
+                .condition(and!(
+                    UserSession::F.user.equals(username),
+                    UserSession::F.id.not_equals(keep)
+                ))
+                .await?
+        }
+        None => {
+            delete!(db, UserSession)
+                .condition(UserSession::F.user.equals(username))
+                .await?
+        }
+    };
+
+    Ok(())
+}
+
+/// Store a new password hash and revoke everything granted with the old password
+///
+/// Every session except the one with the id `keep` and every api token of the user
+/// are deleted in the same transaction, so a leaked credential stops working
+/// as soon as the new password does.
+pub(crate) async fn replace_password(
+    db: &Database,
+    username: &str,
+    password_hash: &str,
+    keep: Option<&str>,
+) -> Result<(), rorm::Error> {
+    let mut tx = db.start_transaction().await?;
+
+    update!(db, User)
+        .transaction(&mut tx)
+        .set(User::F.password_hash, password_hash)
+        .condition(User::F.username.equals(username))
+        .exec()
+        .await?;
+    match keep {
+        Some(keep) => {
+            delete!(db, UserSession)
+                .transaction(&mut tx)
+                .condition(and!(
+                    UserSession::F.user.equals(username),
+                    UserSession::F.id.not_equals(keep)
+                ))
+                .await?
+        }
+        None => {
+            delete!(db, UserSession)
+                .transaction(&mut tx)
+                .condition(UserSession::F.user.equals(username))
+                .await?
+        }
+    };
+    delete!(db, ApiToken)
+        .transaction(&mut tx)
+        .condition(ApiToken::F.user.equals(username))
+        .await?;
+
+    tx.commit().await
+}
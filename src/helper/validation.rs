@@ -0,0 +1,70 @@
+use unicode_normalization::UnicodeNormalization;
+
+use crate::models::config::DisplayNameConfig;
+
+/// Maximum length of usernames and display names in bytes, matches the database column
+const MAX_NAME_LENGTH: usize = 255;
+
+/// Check whether a username is acceptable
+///
+/// Usernames are used as identifiers, so they are restricted to ascii
+/// alphanumerics, `-`, `_` and `.`.
+pub(crate) fn valid_username(username: &str) -> bool {
+    !username.is_empty()
+        && username.len() <= MAX_NAME_LENGTH
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Rules display names have to follow
+pub(crate) struct DisplayNamePolicy {
+    min_length: usize,
+    max_length: usize,
+    /// Lowercase and normalized, to be compared with the lowercase display name
+    banned_words: Vec<String>,
+}
+
+impl DisplayNamePolicy {
+    pub(crate) fn new(config: &DisplayNameConfig) -> Self {
+        Self {
+            min_length: config.min_length,
+            max_length: config.max_length,
+            banned_words: config
+                .banned_words
+                .iter()
+                .map(|word| word.nfkc().collect::<String>().to_lowercase())
+                .filter(|word| !word.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Normalize a display name and check it against the policy
+    ///
+    /// Returns the name which should be stored or `None` if it isn't acceptable.
+    /// The name is NFKC normalized, so visually identical names compare equal
+    /// and banned words can't be hidden behind compatibility characters.
+    pub(crate) fn normalize(&self, display_name: &str) -> Option<String> {
+        let display_name = display_name.nfkc().collect::<String>().trim().to_string();
+
+        let length = display_name.chars().count();
+        if length < self.min_length.max(1)
+            || length > self.max_length
+            || display_name.len() > MAX_NAME_LENGTH
+            || display_name.chars().any(char::is_control)
+        {
+            return None;
+        }
+
+        let lowercase = display_name.to_lowercase();
+        if self
+            .banned_words
+            .iter()
+            .any(|word| lowercase.contains(word.as_str()))
+        {
+            return None;
+        }
+
+        Some(display_name)
+    }
+}
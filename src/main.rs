@@ -13,14 +13,21 @@ use clap::{Parser, Subcommand};
 use log::{error, info, LevelFilter};
 use rorm::{Database, DatabaseConfiguration, DatabaseDriver};
 
+use crate::helper::{DisplayNamePolicy, PasswordHashing};
 use crate::models::config::Config;
+use crate::models::db::Role;
+use crate::region::Region;
 use crate::server::start_server;
 
 mod handler;
 mod helper;
 mod models;
+mod osm_change;
+mod osm_pbf;
 mod parse_osm;
+mod region;
 mod server;
+mod user_management;
 mod world;
 
 const LOGO: &str = r#" ______
@@ -52,22 +59,109 @@ enum Command {
         file: String,
 
         /// Longitude of center
-        #[clap(long, help = "Longitude of center point to use")]
-        center_y: f64,
+        #[clap(long, alias = "center-x", requires = "center_lat")]
+        #[clap(required_unless_present_any = ["bbox", "boundary"])]
+        #[clap(help = "Longitude of the center of the grid to import")]
+        center_lng: Option<f64>,
 
         /// Latitude of center
-        #[clap(long, help = "Latitude of center point to use")]
-        center_x: f64,
+        #[clap(long, alias = "center-y", requires = "center_lng")]
+        #[clap(help = "Latitude of the center of the grid to import")]
+        center_lat: Option<f64>,
+
+        /// Bounding box to import
+        #[clap(long, conflicts_with_all = ["center_lng", "center_lat", "boundary"])]
+        #[clap(help = "Import the bounding box given as min_lng,min_lat,max_lng,max_lat")]
+        bbox: Option<String>,
+
+        /// Boundary file of the region to import
+        #[clap(long, conflicts_with_all = ["center_lng", "center_lat"])]
+        #[clap(help = "Import the polygon in a .poly or GeoJSON file")]
+        boundary: Option<String>,
 
         /// Number of columns
         #[clap(long, value_parser, default_value_t = 32)]
-        #[clap(help = "Number of columns to generate")]
+        #[clap(help = "Number of columns to generate around the center")]
         cols: usize,
 
         /// Number of rows
         #[clap(long, value_parser, default_value_t = 32)]
-        #[clap(help = "Number of rows to generate")]
+        #[clap(help = "Number of rows to generate around the center")]
         rows: usize,
+
+        /// Number of rows per insert
+        #[clap(long, value_parser, default_value_t = 1000)]
+        #[clap(help = "Number of rows written to the database at once")]
+        batch_size: usize,
+
+        /// Continue an interrupted import
+        #[clap(long)]
+        #[clap(help = "Continue an interrupted import of the same file and region")]
+        resume: bool,
+    },
+    /// Update the imported world with an OsmChange file
+    ApplyOsmDiff {
+        #[clap(default_value_t = String::from("/etc/rustymon-server/config.toml"))]
+        #[clap(long = "config-path")]
+        #[clap(help = "Specify an alternative path to the configuration file.")]
+        config_path: String,
+
+        /// PBF file to parse
+        #[clap(long)]
+        #[clap(help = "PBF file the change has already been applied to")]
+        file: String,
+
+        /// OsmChange file
+        #[clap(long)]
+        #[clap(help = "OsmChange (.osc) file whose touched tiles are updated")]
+        diff: String,
+
+        /// Number of rows per insert
+        #[clap(long, value_parser, default_value_t = 1000)]
+        #[clap(help = "Number of rows written to the database at once")]
+        batch_size: usize,
+    },
+    /// Create a new user, the password is read from stdin
+    CreateUser {
+        #[clap(default_value_t = String::from("/etc/rustymon-server/config.toml"))]
+        #[clap(long = "config-path")]
+        #[clap(help = "Specify an alternative path to the configuration file.")]
+        config_path: String,
+
+        /// Name of the new user
+        #[clap(help = "Name of the new user")]
+        username: String,
+
+        /// Display name of the new user
+        #[clap(long, help = "Display name of the new user, defaults to the username")]
+        display_name: Option<String>,
+
+        /// Role of the new user
+        #[clap(long, value_enum, default_value_t = Role::Player)]
+        #[clap(help = "Role of the new user")]
+        role: Role,
+    },
+    /// Delete an existing user
+    DeleteUser {
+        #[clap(default_value_t = String::from("/etc/rustymon-server/config.toml"))]
+        #[clap(long = "config-path")]
+        #[clap(help = "Specify an alternative path to the configuration file.")]
+        config_path: String,
+
+        /// Name of the user to delete
+        #[clap(help = "Name of the user to delete")]
+        username: String,
+    },
+    /// Set a new password for an existing user, the password is read from stdin
+    ResetPassword {
+        #[clap(default_value_t = String::from("/etc/rustymon-server/config.toml"))]
+        #[clap(long = "config-path")]
+        #[clap(help = "Specify an alternative path to the configuration file.")]
+        config_path: String,
+
+        /// Name of the user whose password should be reset
+        #[clap(help = "Name of the user whose password should be reset")]
+        username: String,
     },
 }
 
@@ -138,14 +232,86 @@ async fn main() -> Result<(), String> {
             file,
             cols,
             rows,
-            center_x,
-            center_y,
+            center_lng,
+            center_lat,
+            bbox,
+            boundary,
+            batch_size,
+            resume,
+            config_path,
+        } => {
+            let region = match (center_lng.zip(center_lat), bbox, boundary) {
+                (_, Some(bbox), _) => Region::bounding_box(&bbox)?,
+                (_, _, Some(boundary)) => Region::boundary_file(&boundary)?,
+                (Some((lng, lat)), _, _) => Region::grid(lng, lat, cols, rows)?,
+                (None, None, None) => return Err("No region to import was given".to_string()),
+            };
+
+            let config = get_config(&config_path)?;
+            let db = init_db(&config).await?;
+
+            parse_osm::parse_osm(
+                db,
+                file,
+                region,
+                parse_osm::ImportOptions { batch_size, resume },
+            )
+            .await
+        }
+        Command::ApplyOsmDiff {
+            config_path,
+            file,
+            diff,
+            batch_size,
+        } => {
+            let config = get_config(&config_path)?;
+            let db = init_db(&config).await?;
+
+            parse_osm::apply_osm_diff(
+                db,
+                file,
+                diff,
+                parse_osm::ImportOptions {
+                    batch_size,
+                    resume: false,
+                },
+            )
+            .await
+        }
+        Command::CreateUser {
+            config_path,
+            username,
+            display_name,
+            role,
+        } => {
+            let config = get_config(&config_path)?;
+            let db = init_db(&config).await?;
+
+            let hashing = PasswordHashing::new(&config.argon2)?;
+            let display_names = DisplayNamePolicy::new(&config.display_name);
+
+            user_management::create_user(db, hashing, display_names, username, display_name, role)
+                .await
+        }
+        Command::DeleteUser {
+            config_path,
+            username,
+        } => {
+            let config = get_config(&config_path)?;
+            let db = init_db(&config).await?;
+
+            user_management::delete_user(db, username).await
+        }
+        Command::ResetPassword {
             config_path,
+            username,
         } => {
             let config = get_config(&config_path)?;
             let db = init_db(&config).await?;
 
-            parse_osm::parse_osm(db, file, cols, rows, center_x, center_y).await
+            let hashing = PasswordHashing::new(&config.argon2)?;
+
+            user_management::reset_password(db, hashing, username).await
         }
     }
 }
@@ -1,4 +1,8 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+
 use actix_toolbox::logging::LoggingConfig;
+use argon2::Params;
 use serde::Deserialize;
 
 #[derive(Debug, Clone, Deserialize)]
@@ -19,10 +23,142 @@ pub(crate) struct DBConfig {
     pub(crate) name: String,
 }
 
+/// Limits for failed login attempts
+///
+/// Once a username or a client ip exceeds its number of free attempts,
+/// every further failure locks it for `BaseLockout` seconds,
+/// doubling with each failure up to `MaxLockout` seconds.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub(crate) struct LoginProtection {
+    /// Failed attempts per username before the lockout starts
+    pub(crate) user_attempts: u32,
+    /// Failed attempts per client ip before the lockout starts
+    pub(crate) ip_attempts: u32,
+    /// Duration of the first lockout in seconds
+    pub(crate) base_lockout: u64,
+    /// Upper bound of a single lockout in seconds
+    pub(crate) max_lockout: u64,
+    /// Seconds without a failed attempt after which the failures are forgotten
+    pub(crate) reset_after: u64,
+    /// Maximum number of usernames, and as many client ips, whose failures are tracked at once
+    pub(crate) max_tracked: usize,
+    /// Reverse proxies whose forwarded client ip is used instead of their own address
+    pub(crate) trusted_proxies: Vec<IpAddr>,
+}
+
+impl Default for LoginProtection {
+    fn default() -> Self {
+        Self {
+            user_attempts: 5,
+            ip_attempts: 20,
+            base_lockout: 30,
+            max_lockout: 60 * 60,
+            reset_after: 60 * 60,
+            max_tracked: 100_000,
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+/// Cost parameters used to hash new passwords
+///
+/// Existing hashes with lower costs are upgraded on the next login.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub(crate) struct Argon2Config {
+    /// Memory size in KiB
+    pub(crate) memory_cost: u32,
+    /// Number of iterations
+    pub(crate) time_cost: u32,
+    /// Degree of parallelism
+    pub(crate) parallelism: u32,
+}
+
+impl Default for Argon2Config {
+    fn default() -> Self {
+        Self {
+            memory_cost: Params::DEFAULT_M_COST,
+            time_cost: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// Rules for the display names chosen by users
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub(crate) struct DisplayNameConfig {
+    /// Minimum length in characters
+    pub(crate) min_length: usize,
+    /// Maximum length in characters
+    pub(crate) max_length: usize,
+    /// Words which must not be contained in a display name, compared case insensitive
+    pub(crate) banned_words: Vec<String>,
+}
+
+impl Default for DisplayNameConfig {
+    fn default() -> Self {
+        Self {
+            min_length: 3,
+            max_length: 32,
+            banned_words: Vec::new(),
+        }
+    }
+}
+
+/// Settings of the world api
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub(crate) struct WorldConfig {
+    /// Maximum number of features returned by a single geometry request
+    pub(crate) max_features: usize,
+    /// Largest radius in metres a nearby search may use
+    pub(crate) max_nearby_radius: f64,
+    /// Maximum number of features returned by a nearby search
+    pub(crate) max_nearby_results: usize,
+    /// Distance in metres within which a node counts as being at a point
+    pub(crate) node_distance: f64,
+    /// Distance in metres within which a way counts as being at a point
+    pub(crate) way_distance: f64,
+    /// Distances in metres replacing the defaults for features with one of these OSM keys
+    ///
+    /// Areas only count as being at a point if they contain it,
+    /// unless one of their keys is listed here.
+    pub(crate) key_distances: HashMap<String, f64>,
+    /// Number of decoded tiles kept in memory
+    pub(crate) tile_cache_size: usize,
+    /// Seconds between checks whether the world has been re-imported
+    pub(crate) revision_check_interval: u64,
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        Self {
+            max_features: 10_000,
+            max_nearby_radius: 5_000.0,
+            max_nearby_results: 100,
+            node_distance: 10.0,
+            way_distance: 10.0,
+            key_distances: HashMap::new(),
+            tile_cache_size: 1024,
+            revision_check_interval: 30,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub(crate) struct Config {
     pub(crate) server: Server,
     pub(crate) database: DBConfig,
     pub(crate) logging: LoggingConfig,
+    #[serde(default)]
+    pub(crate) login_protection: LoginProtection,
+    #[serde(default)]
+    pub(crate) argon2: Argon2Config,
+    #[serde(default)]
+    pub(crate) display_name: DisplayNameConfig,
+    #[serde(default)]
+    pub(crate) world: WorldConfig,
 }
@@ -1,15 +1,32 @@
-use rorm::{ForeignModel, Model, Patch};
-use rustymon_world::features::{prototyping, FeatureParser};
-use rustymon_world::formats;
+use rorm::{DbEnum, ForeignModel, Model, Patch};
 use rustymon_world::geometry::Point;
 use serde::{Deserialize, Serialize};
 
-type ParsedArea<'tile> =
-    formats::Item<&'tile <prototyping::Parser as FeatureParser>::Feature, &'tile [Point]>;
-type ParsedNode<'tile> =
-    formats::Item<&'tile <prototyping::Parser as FeatureParser>::Feature, &'tile Point>;
-type ParsedWay<'tile> =
-    formats::Item<&'tile <prototyping::Parser as FeatureParser>::Feature, &'tile [Point]>;
+use crate::models::geometry::{self, DecodeError};
+
+/// Permission level of a user
+///
+/// The variants are ordered by privilege, so a higher role includes
+/// every permission of the lower ones.
+#[derive(
+    DbEnum,
+    Serialize,
+    Deserialize,
+    clap::ValueEnum,
+    Copy,
+    Clone,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Role {
+    Player,
+    Moderator,
+    Admin,
+}
 
 #[derive(Model, Serialize, Deserialize)]
 pub(crate) struct User {
@@ -20,15 +37,142 @@ pub(crate) struct User {
     #[rorm(max_length = 1024)]
     pub(crate) password_hash: String,
 
+    #[rorm(default = "Player")]
+    pub(crate) role: Role,
+
+    /// Base32 encoded TOTP secret, if the user enabled two-factor authentication
+    #[rorm(max_length = 255)]
+    pub(crate) totp_secret: Option<String>,
+    /// TOTP counter of the last accepted code, older codes are rejected to prevent replays
+    #[rorm(default = 0)]
+    pub(crate) totp_last_counter: i64,
+
+    #[rorm(auto_create_time)]
+    pub(crate) created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Patch)]
+#[rorm(model = "User")]
+pub(crate) struct UserInsert {
+    pub(crate) username: String,
+    pub(crate) display_name: String,
+    pub(crate) password_hash: String,
+    pub(crate) role: Role,
+}
+
+/// One-time code to log in without the TOTP second factor
+#[derive(Model)]
+pub(crate) struct RecoveryCode {
+    #[rorm(id)]
+    pub(crate) id: i64,
+
+    #[rorm(on_delete = "Cascade", on_update = "Cascade")]
+    pub(crate) user: ForeignModel<User>,
+
+    #[rorm(max_length = 1024)]
+    pub(crate) code_hash: String,
+}
+
+#[derive(Patch)]
+#[rorm(model = "RecoveryCode")]
+pub(crate) struct RecoveryCodeInsert {
+    pub(crate) user: ForeignModel<User>,
+    pub(crate) code_hash: String,
+}
+
+/// A logged in session of a user
+///
+/// The session store itself only knows an opaque state,
+/// this links the session to its user and tracks where it is used.
+#[derive(Model)]
+pub(crate) struct UserSession {
+    #[rorm(primary_key, max_length = 255)]
+    pub(crate) id: String,
+
+    #[rorm(on_delete = "Cascade", on_update = "Cascade")]
+    pub(crate) user: ForeignModel<User>,
+
+    #[rorm(auto_create_time)]
+    pub(crate) created_at: chrono::NaiveDateTime,
+
+    pub(crate) last_seen: chrono::NaiveDateTime,
+
+    #[rorm(max_length = 1024)]
+    pub(crate) user_agent: Option<String>,
+
+    #[rorm(max_length = 255)]
+    pub(crate) ip: Option<String>,
+}
+
+#[derive(Patch)]
+#[rorm(model = "UserSession")]
+pub(crate) struct UserSessionInsert {
+    pub(crate) id: String,
+    pub(crate) user: ForeignModel<User>,
+    pub(crate) last_seen: chrono::NaiveDateTime,
+    pub(crate) user_agent: Option<String>,
+    pub(crate) ip: Option<String>,
+}
+
+/// Personal access token for clients which can't use the session cookie
+#[derive(Model)]
+pub(crate) struct ApiToken {
+    #[rorm(id)]
+    pub(crate) id: i64,
+
+    #[rorm(on_delete = "Cascade", on_update = "Cascade")]
+    pub(crate) user: ForeignModel<User>,
+
+    #[rorm(max_length = 255)]
+    pub(crate) name: String,
+
+    /// Hex encoded sha256 hash of the token
+    #[rorm(max_length = 64, unique)]
+    pub(crate) token_hash: String,
+
+    /// Space separated list of [`TokenScope`](crate::helper::TokenScope)s
+    #[rorm(max_length = 1024)]
+    pub(crate) scopes: String,
+
     #[rorm(auto_create_time)]
     pub(crate) created_at: chrono::NaiveDateTime,
+
+    pub(crate) last_used: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Patch)]
+#[rorm(model = "ApiToken")]
+pub(crate) struct ApiTokenInsert {
+    pub(crate) user: ForeignModel<User>,
+    pub(crate) name: String,
+    pub(crate) token_hash: String,
+    pub(crate) scopes: String,
+    pub(crate) last_used: Option<chrono::NaiveDateTime>,
 }
 
+/// A square section of the world
+///
+/// Tiles are addressed by their slippy map coordinates.
+/// Imports are staged as inactive tiles and activated at once when they are complete,
+/// so the combination of `zoom`, `x`, `y` and `active` is unique.
 #[derive(Model)]
 pub(crate) struct Tile {
     #[rorm(id)]
     pub(crate) id: i64,
 
+    #[rorm(default = true)]
+    pub(crate) active: bool,
+    /// All features of the tile have been written
+    #[rorm(default = true)]
+    pub(crate) complete: bool,
+
+    #[rorm(default = 14)]
+    pub(crate) zoom: i16,
+    #[rorm(default = 0)]
+    pub(crate) x: i64,
+    #[rorm(default = 0)]
+    pub(crate) y: i64,
+
     pub(crate) min_x: f64,
     pub(crate) max_x: f64,
     pub(crate) min_y: f64,
@@ -38,12 +182,63 @@ pub(crate) struct Tile {
 #[derive(Patch)]
 #[rorm(model = "Tile")]
 pub(crate) struct TileInsert {
+    pub(crate) active: bool,
+    pub(crate) complete: bool,
+    pub(crate) zoom: i16,
+    pub(crate) x: i64,
+    pub(crate) y: i64,
     pub(crate) min_x: f64,
     pub(crate) max_x: f64,
     pub(crate) min_y: f64,
     pub(crate) max_y: f64,
 }
 
+/// A completed import of the world
+///
+/// Servers compare the latest revision to notice re-imports.
+#[derive(Model)]
+pub(crate) struct WorldRevision {
+    #[rorm(id)]
+    pub(crate) id: i64,
+
+    /// The imported file
+    #[rorm(max_length = 1024)]
+    pub(crate) source: String,
+
+    #[rorm(auto_create_time)]
+    pub(crate) imported_at: chrono::NaiveDateTime,
+}
+
+#[derive(Patch)]
+#[rorm(model = "WorldRevision")]
+pub(crate) struct WorldRevisionInsert {
+    pub(crate) source: String,
+}
+
+/// The import the staged tiles belong to
+///
+/// An interrupted import may only be resumed with the same file and region.
+#[derive(Model)]
+pub(crate) struct StagedImport {
+    #[rorm(id)]
+    pub(crate) id: i64,
+
+    /// The parsed file
+    #[rorm(max_length = 1024)]
+    pub(crate) file: String,
+
+    /// The imported region, see [`Region::fingerprint`](crate::region::Region::fingerprint)
+    #[rorm(max_length = 255)]
+    pub(crate) region: String,
+}
+
+#[derive(Patch)]
+#[rorm(model = "StagedImport")]
+pub(crate) struct StagedImportInsert {
+    pub(crate) file: String,
+    pub(crate) region: String,
+}
+
 #[derive(Model)]
 pub(crate) struct Way {
     #[rorm(id)]
@@ -64,13 +259,11 @@ pub(crate) struct WayInsert {
     features: Vec<u8>,
 }
 impl WayInsert {
-    pub(crate) fn new(tile: i64, way: ParsedWay) -> Self {
-        unsafe {
-            Self {
-                tile: ForeignModel::Key(tile),
-                points: bytes_from_slice(way.points).to_vec(),
-                features: bytes_from_slice(way.feature).to_vec(),
-            }
+    pub(crate) fn from_parts(tile: i64, points: &[Point], features: &[[u32; 2]]) -> Self {
+        Self {
+            tile: ForeignModel::Key(tile),
+            points: geometry::encode_points(points),
+            features: geometry::encode_features(features),
         }
     }
 }
@@ -94,13 +287,11 @@ pub(crate) struct AreaInsert {
     features: Vec<u8>,
 }
 impl AreaInsert {
-    pub(crate) fn new(tile: i64, area: ParsedArea) -> Self {
-        unsafe {
-            Self {
-                tile: ForeignModel::Key(tile),
-                points: bytes_from_slice(area.points).to_vec(),
-                features: bytes_from_slice(area.feature).to_vec(),
-            }
+    pub(crate) fn from_parts(tile: i64, points: &[Point], features: &[[u32; 2]]) -> Self {
+        Self {
+            tile: ForeignModel::Key(tile),
+            points: geometry::encode_points(points),
+            features: geometry::encode_features(features),
         }
     }
 }
@@ -128,14 +319,12 @@ pub(crate) struct NodeInsert {
     features: Vec<u8>,
 }
 impl NodeInsert {
-    pub(crate) fn new(tile: i64, node: ParsedNode) -> Self {
-        unsafe {
-            Self {
-                tile: ForeignModel::Key(tile),
-                x: node.points.x,
-                y: node.points.y,
-                features: bytes_from_slice(node.feature).to_vec(),
-            }
+    pub(crate) fn from_parts(tile: i64, point: Point, features: &[[u32; 2]]) -> Self {
+        Self {
+            tile: ForeignModel::Key(tile),
+            x: point.x,
+            y: point.y,
+            features: geometry::encode_features(features),
         }
     }
 }
@@ -144,8 +333,8 @@ macro_rules! impl_features_getter {
     ($($strct:ty),*) => {
         $(
             impl $strct {
-                 pub(crate) fn features(&self) -> &[[u32; 2]] {
-                    unsafe { slice_from_bytes(&self.features) }
+                 pub(crate) fn features(&self) -> Result<Vec<[u32; 2]>, DecodeError> {
+                    geometry::decode_features(&self.features)
                  }
             }
         )*
@@ -157,8 +346,8 @@ macro_rules! impl_points_getter {
     ($($strct:ty),*) => {
         $(
             impl $strct {
-                 pub(crate) fn points(&self) -> &[Point] {
-                    unsafe { slice_from_bytes(&self.points) }
+                 pub(crate) fn points(&self) -> Result<Vec<Point>, DecodeError> {
+                    geometry::decode_points(&self.points)
                 }
             }
         )*
@@ -166,15 +355,35 @@ macro_rules! impl_points_getter {
 }
 impl_points_getter![Area, Way];
 
-unsafe fn slice_from_bytes<T>(bytes: &[u8]) -> &[T] {
-    std::slice::from_raw_parts(
-        bytes.as_ptr() as *const T,
-        bytes.len() / std::mem::size_of::<T>(),
-    )
-}
-unsafe fn bytes_from_slice<T>(bytes: &[T]) -> &[u8] {
-    std::slice::from_raw_parts(
-        bytes.as_ptr() as *const u8,
-        bytes.len() * std::mem::size_of::<T>(),
-    )
+/// Type of an OpenStreetMap element
+#[derive(DbEnum, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum ElementKind {
+    Node,
+    Way,
+    Relation,
+}
+
+/// An OpenStreetMap element the tiles below a tile at the lowest generated zoom level contain
+///
+/// Change files don't carry the previous position of the elements they modify or delete,
+/// so the tiles to update are found by the elements' ids.
+/// Untagged nodes are only recorded through their ways.
+#[derive(Model)]
+pub(crate) struct TileElement {
+    #[rorm(id)]
+    pub(crate) id: i64,
+
+    #[rorm(on_update = "Cascade")]
+    pub(crate) tile: ForeignModel<Tile>,
+
+    pub(crate) kind: ElementKind,
+    pub(crate) osm_id: i64,
+}
+
+#[derive(Patch)]
+#[rorm(model = "TileElement")]
+pub(crate) struct TileElementInsert {
+    pub(crate) tile: ForeignModel<Tile>,
+    pub(crate) kind: ElementKind,
+    pub(crate) osm_id: i64,
 }
@@ -0,0 +1,183 @@
+//! Binary encoding of the geometry and tags stored in the world tables
+//!
+//! Every blob starts with the two bytes `RW` and a version byte,
+//! followed by the items as little-endian numbers without any padding:
+//!
+//! - points are pairs of `f64` (x, y)
+//! - features are pairs of `u32` (key, value)
+
+use std::fmt::{Display, Formatter};
+
+use rustymon_world::geometry::Point;
+
+/// Marker at the start of every blob
+const MAGIC: [u8; 2] = *b"RW";
+
+/// Current version of the encoding
+const VERSION: u8 = 1;
+
+const HEADER_LEN: usize = MAGIC.len() + 1;
+const POINT_LEN: usize = 16;
+const FEATURE_LEN: usize = 8;
+
+/// Reasons a stored blob can't be decoded
+#[derive(Debug)]
+pub(crate) enum DecodeError {
+    /// The blob doesn't start with the magic bytes
+    MissingHeader,
+    /// The blob was written by an unknown version of the encoding
+    UnknownVersion(u8),
+    /// The payload isn't a whole number of items
+    InvalidLength(usize),
+    /// A coordinate is NaN or infinite
+    NonFinitePoint,
+    /// The blob contains no points, although every geometry has at least one
+    NoPoints,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::MissingHeader => write!(f, "missing header"),
+            DecodeError::UnknownVersion(version) => write!(f, "unknown version {version}"),
+            DecodeError::InvalidLength(len) => write!(f, "invalid payload length {len}"),
+            DecodeError::NonFinitePoint => write!(f, "non-finite coordinate"),
+            DecodeError::NoPoints => write!(f, "no points"),
+        }
+    }
+}
+
+fn encode<T>(items: &[T], item_len: usize, write: impl Fn(&T, &mut Vec<u8>)) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN + items.len() * item_len);
+    bytes.extend_from_slice(&MAGIC);
+    bytes.push(VERSION);
+    for item in items {
+        write(item, &mut bytes);
+    }
+    bytes
+}
+
+/// Validate the header and split the payload into items
+fn decode(bytes: &[u8], item_len: usize) -> Result<std::slice::ChunksExact<'_, u8>, DecodeError> {
+    let payload = bytes
+        .strip_prefix(&MAGIC)
+        .ok_or(DecodeError::MissingHeader)?;
+    let (&version, payload) = payload.split_first().ok_or(DecodeError::MissingHeader)?;
+    if version != VERSION {
+        return Err(DecodeError::UnknownVersion(version));
+    }
+    if payload.len() % item_len != 0 {
+        return Err(DecodeError::InvalidLength(payload.len()));
+    }
+    Ok(payload.chunks_exact(item_len))
+}
+
+fn read_f64(bytes: &[u8]) -> f64 {
+    f64::from_le_bytes(bytes.try_into().expect("chunk should have 8 bytes"))
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes.try_into().expect("chunk should have 4 bytes"))
+}
+
+pub(crate) fn encode_points(points: &[Point]) -> Vec<u8> {
+    encode(points, POINT_LEN, |point, bytes| {
+        bytes.extend_from_slice(&point.x.to_le_bytes());
+        bytes.extend_from_slice(&point.y.to_le_bytes());
+    })
+}
+
+pub(crate) fn decode_points(bytes: &[u8]) -> Result<Vec<Point>, DecodeError> {
+    let points = decode(bytes, POINT_LEN)?
+        .map(|chunk| {
+            let (x, y) = chunk.split_at(8);
+            let point = Point::new(read_f64(x), read_f64(y));
+            if point.x.is_finite() && point.y.is_finite() {
+                Ok(point)
+            } else {
+                Err(DecodeError::NonFinitePoint)
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if points.is_empty() {
+        return Err(DecodeError::NoPoints);
+    }
+    Ok(points)
+}
+
+pub(crate) fn encode_features(features: &[[u32; 2]]) -> Vec<u8> {
+    encode(features, FEATURE_LEN, |[key, value], bytes| {
+        bytes.extend_from_slice(&key.to_le_bytes());
+        bytes.extend_from_slice(&value.to_le_bytes());
+    })
+}
+
+pub(crate) fn decode_features(bytes: &[u8]) -> Result<Vec<[u32; 2]>, DecodeError> {
+    Ok(decode(bytes, FEATURE_LEN)?
+        .map(|chunk| {
+            let (key, value) = chunk.split_at(4);
+            [read_u32(key), read_u32(value)]
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points_round_trip() {
+        let points = vec![
+            Point::new(0.0, 0.0),
+            Point::new(0.25, 0.75),
+            Point::new(-1.5, f64::MAX),
+        ];
+        let bytes = encode_points(&points);
+
+        assert_eq!(bytes.len(), HEADER_LEN + points.len() * POINT_LEN);
+        assert_eq!(decode_points(&bytes).unwrap(), points);
+    }
+
+    #[test]
+    fn features_round_trip() {
+        let features = vec![[0, 0], [1, 2], [u32::MAX, 7]];
+        let bytes = encode_features(&features);
+
+        assert_eq!(decode_features(&bytes).unwrap(), features);
+        assert!(decode_features(&encode_features(&[])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn empty_points_are_rejected() {
+        assert!(matches!(
+            decode_points(&encode_points(&[])),
+            Err(DecodeError::NoPoints)
+        ));
+    }
+
+    #[test]
+    fn invalid_blobs_are_rejected() {
+        let mut bytes = encode_points(&[Point::new(1.0, 2.0)]);
+
+        assert!(matches!(
+            decode_points(&bytes[1..]),
+            Err(DecodeError::MissingHeader)
+        ));
+        assert!(matches!(
+            decode_points(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::InvalidLength(15))
+        ));
+
+        bytes[HEADER_LEN..HEADER_LEN + 8].copy_from_slice(&f64::NAN.to_le_bytes());
+        assert!(matches!(
+            decode_points(&bytes),
+            Err(DecodeError::NonFinitePoint)
+        ));
+
+        bytes[MAGIC.len()] = VERSION + 1;
+        assert!(matches!(
+            decode_points(&bytes),
+            Err(DecodeError::UnknownVersion(2))
+        ));
+    }
+}
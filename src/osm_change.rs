@@ -0,0 +1,314 @@
+//! Reading OsmChange files to find the parts of the world they touch
+//!
+//! See <https://wiki.openstreetmap.org/wiki/OsmChange>
+
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use rustymon_world::geometry::Point;
+
+use crate::models::db::ElementKind;
+use crate::osm_pbf;
+use crate::world::Coord;
+
+/// A relation's members which can be located
+#[derive(Default)]
+struct Relation {
+    nodes: Vec<i64>,
+    ways: Vec<i64>,
+}
+
+/// Way or relation whose children are being read
+enum Element {
+    Way(i64, Vec<i64>),
+    Relation(i64, Relation),
+}
+
+/// The elements created, modified or deleted by an OsmChange file
+#[derive(Default)]
+pub(crate) struct OsmChange {
+    /// Positions of the changed nodes, deleted nodes don't always carry one
+    nodes: HashMap<i64, Option<Coord>>,
+    /// Node references of the changed ways
+    ways: HashMap<i64, Vec<i64>>,
+    relations: HashMap<i64, Relation>,
+}
+
+/// Where a change applies
+pub(crate) struct Located {
+    /// Projected positions of the changed elements after the change
+    pub(crate) points: Vec<Point>,
+    /// Elements whose tiles from before the change have to be updated
+    pub(crate) elements: Vec<(ElementKind, i64)>,
+}
+
+impl OsmChange {
+    /// Read an OsmChange file
+    ///
+    /// Only the ids, positions and references are kept, tags are ignored.
+    /// Retagged elements are found by their ids all the same.
+    pub(crate) fn read(path: &str) -> Result<Self, String> {
+        let mut reader =
+            Reader::from_file(path).map_err(|e| format!("Could not open the change file: {e}"))?;
+        reader.trim_text(true);
+
+        let mut change = Self::default();
+        let mut current = None;
+        let mut buf = Vec::new();
+        loop {
+            let event = reader.read_event_into(&mut buf).map_err(|e| {
+                format!(
+                    "Invalid change file at position {}: {e}",
+                    reader.buffer_position()
+                )
+            })?;
+            match event {
+                Event::Start(element) => change.start(&element, &mut current)?,
+                Event::Empty(element) => {
+                    change.start(&element, &mut current)?;
+                    change.end(element.name().as_ref(), &mut current);
+                }
+                Event::End(element) => change.end(element.name().as_ref(), &mut current),
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+        Ok(change)
+    }
+
+    fn start(&mut self, element: &BytesStart, current: &mut Option<Element>) -> Result<(), String> {
+        match element.name().as_ref() {
+            b"node" => {
+                let id = required(element, b"id")?;
+                let position = match (attribute(element, b"lat")?, attribute(element, b"lon")?) {
+                    (Some(lat), Some(lng)) => Some(Coord { lat, lng }),
+                    _ => None,
+                };
+                self.nodes.insert(id, position);
+            }
+            b"way" => *current = Some(Element::Way(required(element, b"id")?, Vec::new())),
+            b"nd" => {
+                if let Some(Element::Way(_, refs)) = current {
+                    refs.push(required(element, b"ref")?);
+                }
+            }
+            b"relation" => {
+                *current = Some(Element::Relation(
+                    required(element, b"id")?,
+                    Relation::default(),
+                ))
+            }
+            b"member" => {
+                if let Some(Element::Relation(_, relation)) = current {
+                    let id = required(element, b"ref")?;
+                    match attribute::<String>(element, b"type")?.as_deref() {
+                        Some("node") => relation.nodes.push(id),
+                        Some("way") => relation.ways.push(id),
+                        // Nested relations aren't followed
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn end(&mut self, name: &[u8], current: &mut Option<Element>) {
+        match (name, current.take()) {
+            (b"way", Some(Element::Way(id, refs))) => {
+                self.ways.insert(id, refs);
+            }
+            (b"relation", Some(Element::Relation(id, relation))) => {
+                self.relations.insert(id, relation);
+            }
+            (_, element) => *current = element,
+        }
+    }
+
+    /// Get the ids of every changed element
+    pub(crate) fn elements(&self) -> impl Iterator<Item = (ElementKind, i64)> + '_ {
+        let nodes = self.nodes.keys().map(|&id| (ElementKind::Node, id));
+        let ways = self.ways.keys().map(|&id| (ElementKind::Way, id));
+        let relations = self.relations.keys().map(|&id| (ElementKind::Relation, id));
+        nodes.chain(ways).chain(relations)
+    }
+
+    /// Get the ways which aren't part of the change, but use one of its nodes
+    ///
+    /// `ways` are node references after the change.
+    /// The geometry of these ways changes with their nodes, although the ways themselves don't.
+    fn moved_ways<'a>(
+        &'a self,
+        ways: &'a HashMap<i64, Vec<i64>>,
+    ) -> impl Iterator<Item = i64> + 'a {
+        ways.iter()
+            .filter(|&(id, refs)| !self.ways.contains_key(id) && self.uses_changed_node(refs))
+            .map(|(&id, _)| id)
+    }
+
+    fn uses_changed_node(&self, refs: &[i64]) -> bool {
+        refs.iter().any(|id| self.nodes.contains_key(id))
+    }
+
+    /// Find where the change applies
+    ///
+    /// `file` has to contain the data with the change applied.
+    /// It provides the positions of the nodes the change only references,
+    /// e.g. of a way whose tags changed, the ways using a changed node
+    /// and the relations with a changed member.
+    /// Deleted elements can't be located by their position,
+    /// their previous tiles are found by [`Located::elements`].
+    ///
+    /// This reads the whole file three times, so it should be run on a blocking thread.
+    pub(crate) fn locate(&self, file: &str) -> Result<Located, String> {
+        let member_ways: HashSet<_> = self
+            .relations
+            .values()
+            .flat_map(|relation| relation.ways.iter().copied())
+            .collect();
+        let ways = osm_pbf::way_refs(file, |id, refs| {
+            self.ways.contains_key(&id) || member_ways.contains(&id) || self.uses_changed_node(refs)
+        })?;
+
+        let nodes: HashSet<_> = ways
+            .values()
+            .flatten()
+            .copied()
+            .chain(
+                self.relations
+                    .values()
+                    .flat_map(|relation| relation.nodes.iter().copied()),
+            )
+            .chain(self.nodes.keys().copied())
+            .collect();
+        // Deleted nodes are missing from the file, but may carry their last position
+        let points = osm_pbf::node_positions(file, &nodes)?
+            .iter()
+            .chain(self.nodes.values().flatten())
+            // Nodes outside the projection's range can't be part of the world
+            .filter_map(Coord::project)
+            .collect();
+
+        let moved: HashSet<_> = self.moved_ways(&ways).collect();
+        let relations = osm_pbf::relations_referencing(file, |kind, id| match kind {
+            ElementKind::Node => self.nodes.contains_key(&id),
+            ElementKind::Way => self.ways.contains_key(&id) || moved.contains(&id),
+            ElementKind::Relation => false,
+        })?;
+
+        let mut elements: Vec<_> = self.elements().collect();
+        elements.extend(moved.into_iter().map(|id| (ElementKind::Way, id)));
+        elements.extend(
+            relations
+                .into_iter()
+                .filter(|id| !self.relations.contains_key(id))
+                .map(|id| (ElementKind::Relation, id)),
+        );
+        Ok(Located { points, elements })
+    }
+}
+
+/// Parse an attribute of an element
+fn attribute<T: FromStr>(element: &BytesStart, name: &[u8]) -> Result<Option<T>, String> {
+    let name_str = String::from_utf8_lossy(name);
+    for attribute in element.attributes() {
+        let attribute = attribute.map_err(|e| format!("Invalid attribute: {e}"))?;
+        if attribute.key.as_ref() == name {
+            let value = attribute
+                .unescape_value()
+                .map_err(|e| format!("Invalid value of {name_str}: {e}"))?;
+            return value
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("Invalid value of {name_str}: {value}"));
+        }
+    }
+    Ok(None)
+}
+
+/// Parse an attribute which has to be present
+fn required<T: FromStr>(element: &BytesStart, name: &[u8]) -> Result<T, String> {
+    attribute(element, name)?.ok_or_else(|| {
+        format!(
+            "Missing attribute {} of {}",
+            String::from_utf8_lossy(name),
+            String::from_utf8_lossy(element.name().as_ref())
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANGE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osmChange version="0.6">
+  <create>
+    <node id="1" lat="52.5" lon="13.4"/>
+  </create>
+  <modify>
+    <way id="10">
+      <nd ref="1"/>
+      <nd ref="2"/>
+      <tag k="highway" v="path"/>
+    </way>
+  </modify>
+  <delete>
+    <node id="3"/>
+    <relation id="20">
+      <member type="way" ref="12" role="outer"/>
+      <member type="node" ref="4" role=""/>
+    </relation>
+  </delete>
+</osmChange>
+"#;
+
+    fn read(name: &str, content: &str) -> Result<OsmChange, String> {
+        let path = std::env::temp_dir().join(format!("rustymon-{name}-{}.osc", std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        let change = OsmChange::read(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        change
+    }
+
+    #[test]
+    fn elements_of_every_action() {
+        let change = read("elements", CHANGE).unwrap();
+
+        let mut elements: Vec<_> = change.elements().collect();
+        elements.sort_by_key(|&(kind, id)| (kind as u8, id));
+        assert_eq!(
+            elements,
+            [
+                (ElementKind::Node, 1),
+                (ElementKind::Node, 3),
+                (ElementKind::Way, 10),
+                (ElementKind::Relation, 20),
+            ]
+        );
+        assert_eq!(change.nodes[&1].as_ref().map(|coord| coord.lat), Some(52.5));
+        assert!(change.nodes[&3].is_none());
+        assert_eq!(change.ways[&10], [1, 2]);
+        assert_eq!(change.relations[&20].ways, [12]);
+        assert_eq!(change.relations[&20].nodes, [4]);
+    }
+
+    #[test]
+    fn ways_using_changed_nodes() {
+        let change = read("moved", CHANGE).unwrap();
+        let ways = HashMap::from([(10, vec![1, 2]), (11, vec![2, 3]), (12, vec![4, 5])]);
+
+        let moved: Vec<_> = change.moved_ways(&ways).collect();
+        assert_eq!(moved, [11]);
+    }
+
+    #[test]
+    fn invalid_references() {
+        let change = r#"<osmChange><modify><way id="1"><nd ref="x"/></way></modify></osmChange>"#;
+        assert!(read("invalid", change).is_err());
+    }
+}
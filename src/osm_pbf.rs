@@ -0,0 +1,140 @@
+//! Reading element ids and positions from PBF files
+//!
+//! The world parser only yields the geometry of the tiles,
+//! these are needed to map OpenStreetMap elements to the tiles they end up in.
+//! Ways are found by their nodes and relations by their members,
+//! so the files have to be sorted by type as PBF files usually are.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+
+use osmpbf::{Element, ElementReader, RelMemberType};
+
+use crate::models::db::ElementKind;
+use crate::world::Coord;
+
+fn open(file: &str) -> Result<ElementReader<BufReader<File>>, String> {
+    ElementReader::from_path(file).map_err(|e| format!("Could not open {file}: {e}"))
+}
+
+/// Find the elements within a bounding box given as `[min_lng, min_lat, max_lng, max_lat]`
+///
+/// Untagged nodes are left out, they are only found through their ways.
+pub(crate) fn elements_in(
+    file: &str,
+    [min_lng, min_lat, max_lng, max_lat]: [f64; 4],
+) -> Result<Vec<(ElementKind, i64)>, String> {
+    let mut nodes = HashSet::new();
+    let mut ways = HashSet::new();
+    let mut elements = Vec::new();
+    open(file)?
+        .for_each(|element| {
+            let (id, lat, lng, tagged) = match element {
+                Element::Node(node) => (
+                    node.id(),
+                    node.lat(),
+                    node.lon(),
+                    node.tags().next().is_some(),
+                ),
+                Element::DenseNode(node) => (
+                    node.id(),
+                    node.lat(),
+                    node.lon(),
+                    node.tags().next().is_some(),
+                ),
+                Element::Way(way) => {
+                    if way.refs().any(|node| nodes.contains(&node)) {
+                        ways.insert(way.id());
+                        elements.push((ElementKind::Way, way.id()));
+                    }
+                    return;
+                }
+                Element::Relation(relation) => {
+                    let inside = relation.members().any(|member| match member.member_type {
+                        RelMemberType::Node => nodes.contains(&member.member_id),
+                        RelMemberType::Way => ways.contains(&member.member_id),
+                        // Nested relations aren't followed
+                        RelMemberType::Relation => false,
+                    });
+                    if inside {
+                        elements.push((ElementKind::Relation, relation.id()));
+                    }
+                    return;
+                }
+            };
+
+            if (min_lat..=max_lat).contains(&lat) && (min_lng..=max_lng).contains(&lng) {
+                nodes.insert(id);
+                if tagged {
+                    elements.push((ElementKind::Node, id));
+                }
+            }
+        })
+        .map_err(|e| format!("Error while reading {file}: {e}"))?;
+    Ok(elements)
+}
+
+/// Get the node references of the ways selected by `wanted`
+pub(crate) fn way_refs(
+    file: &str,
+    wanted: impl Fn(i64, &[i64]) -> bool,
+) -> Result<HashMap<i64, Vec<i64>>, String> {
+    let mut ways = HashMap::new();
+    open(file)?
+        .for_each(|element| {
+            if let Element::Way(way) = element {
+                let refs: Vec<_> = way.refs().collect();
+                if wanted(way.id(), &refs) {
+                    ways.insert(way.id(), refs);
+                }
+            }
+        })
+        .map_err(|e| format!("Error while reading {file}: {e}"))?;
+    Ok(ways)
+}
+
+/// Get the ids of the relations with a member selected by `referenced`
+///
+/// Nested relations aren't followed.
+pub(crate) fn relations_referencing(
+    file: &str,
+    referenced: impl Fn(ElementKind, i64) -> bool,
+) -> Result<Vec<i64>, String> {
+    let mut relations = Vec::new();
+    open(file)?
+        .for_each(|element| {
+            if let Element::Relation(relation) = element {
+                let found = relation.members().any(|member| match member.member_type {
+                    RelMemberType::Node => referenced(ElementKind::Node, member.member_id),
+                    RelMemberType::Way => referenced(ElementKind::Way, member.member_id),
+                    RelMemberType::Relation => false,
+                });
+                if found {
+                    relations.push(relation.id());
+                }
+            }
+        })
+        .map_err(|e| format!("Error while reading {file}: {e}"))?;
+    Ok(relations)
+}
+
+/// Get the positions of a set of nodes
+///
+/// Nodes missing from the file are skipped.
+pub(crate) fn node_positions(file: &str, ids: &HashSet<i64>) -> Result<Vec<Coord>, String> {
+    let mut positions = Vec::new();
+    open(file)?
+        .for_each(|element| {
+            let (id, lat, lng) = match element {
+                Element::Node(node) => (node.id(), node.lat(), node.lon()),
+                Element::DenseNode(node) => (node.id(), node.lat(), node.lon()),
+                _ => return,
+            };
+            if ids.contains(&id) {
+                positions.push(Coord { lat, lng });
+            }
+        })
+        .map_err(|e| format!("Error while reading {file}: {e}"))?;
+    Ok(positions)
+}
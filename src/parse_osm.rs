@@ -1,78 +1,931 @@
-use rorm::{insert, Database};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use rorm::conditions::DynamicCollection;
+use rorm::transaction::Transaction;
+use rorm::{and, delete, insert, query, update, Database, ForeignModel, Model};
 use rustymon_world::features::prototyping;
+use rustymon_world::geometry::Point;
+use serde_json::json;
+use tokio::sync::mpsc;
 
-use crate::models::db::{AreaInsert, NodeInsert, TileInsert, WayInsert};
-use crate::world::{PROJECTION, TAGS_FILE, ZOOM};
+use crate::models::db::{
+    Area, AreaInsert, ElementKind, Node, NodeInsert, StagedImport, StagedImportInsert, Tile,
+    TileElement, TileElementInsert, TileInsert, Way, WayInsert, WorldRevisionInsert,
+};
+use crate::osm_change::OsmChange;
+use crate::osm_pbf;
+use crate::region::{Region, TileRange};
+use crate::world::pyramid::{self, PyramidTile, Shape};
+use crate::world::{self, TileAddress, PROJECTION, TAGS_FILE, ZOOM};
+
+/// Number of parsed chunks waiting to be written
+///
+/// Once the writer falls behind, the parser blocks until a chunk has been written.
+const CHUNK_BUFFER: usize = 2;
+
+/// Number of chunks cut out of the file by a single run of `osmium extract`
+///
+/// Each run reads the whole file, but osmium's memory use grows with every extract.
+const EXTRACT_BATCH: usize = 64;
+
+/// Number of tile ids combined in a single condition
+///
+/// Stays below SQLite's default limit of 999 bound parameters.
+const ID_BATCH: usize = 500;
+
+/// A parsed tile at full detail
+struct DetailTile {
+    min: Point,
+    max: Point,
+    nodes: Vec<(Point, Vec<[u32; 2]>)>,
+    shapes: PyramidTile,
+}
+
+/// The parsed tiles below a tile at [`MIN_ZOOM`](pyramid::MIN_ZOOM)
+struct Chunk {
+    root: TileAddress,
+    detail: Vec<DetailTile>,
+    /// The OpenStreetMap elements around the chunk
+    elements: Vec<(ElementKind, i64)>,
+}
+
+/// Check whether `osmium` can be run to cut the file into chunks
+fn osmium_available() -> bool {
+    Command::new("osmium")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// Longitude and latitude bounds of a chunk as expected by `osmium extract`
+///
+/// The chunk is grown by a tile, so ways crossing its corners are kept
+/// even if none of their nodes is inside the chunk.
+fn bbox(range: TileRange) -> [f64; 4] {
+    let size = 1.0 / f64::from(1u32 << ZOOM);
+    let min = world::unproject(Point::new(
+        f64::from(range.min_x.saturating_sub(1)) * size,
+        f64::from(range.min_y.saturating_sub(1)) * size,
+    ));
+    let max = world::unproject(Point::new(
+        (f64::from(range.max_x + 1) * size).min(1.0),
+        (f64::from(range.max_y + 1) * size).min(1.0),
+    ));
+    // The projection's y axis points south, so the minimum is the northern edge
+    [min.x, max.y, max.x, min.y]
+}
+
+/// The parts of the file covering a batch of chunks
+///
+/// The files are removed once the batch has been parsed.
+struct Extracts {
+    directory: PathBuf,
+}
+
+impl Extracts {
+    /// Cut the area of each chunk out of `file` in a single run of `osmium extract`
+    fn cut(file: &str, ranges: &[TileRange], batch: usize) -> Result<Self, String> {
+        let directory =
+            std::env::temp_dir().join(format!("rustymon-import-{}-{batch}", std::process::id()));
+        fs::create_dir_all(&directory)
+            .map_err(|e| format!("Could not create the directory for the extracts: {e}"))?;
+        let extracts = Self { directory };
+
+        let config = json!({
+            "directory": extracts.directory.to_string_lossy(),
+            "extracts": ranges
+                .iter()
+                .enumerate()
+                .map(|(index, &range)| json!({
+                    "output": format!("{index}.osm.pbf"),
+                    "bbox": bbox(range),
+                }))
+                .collect::<Vec<_>>(),
+        });
+        let config_file = extracts.directory.join("extracts.json");
+        fs::write(&config_file, config.to_string())
+            .map_err(|e| format!("Could not write the extract config: {e}"))?;
+
+        // The smart strategy completes the ways and multipolygons reaching into a chunk
+        let status = Command::new("osmium")
+            .args([
+                "extract",
+                "--strategy",
+                "smart",
+                "--overwrite",
+                "--no-progress",
+            ])
+            .arg("--config")
+            .arg(&config_file)
+            .arg(file)
+            .status()
+            .map_err(|e| format!("Could not run osmium: {e}"))?;
+        if !status.success() {
+            return Err(format!("osmium extract failed: {status}"));
+        }
+        Ok(extracts)
+    }
+
+    /// Path of the extract of the `index`th chunk
+    fn file(&self, index: usize) -> String {
+        self.directory
+            .join(format!("{index}.osm.pbf"))
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+impl Drop for Extracts {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_dir_all(&self.directory) {
+            if err.kind() != ErrorKind::NotFound {
+                println!("Could not remove {}: {err}", self.directory.display());
+            }
+        }
+    }
+}
+
+/// Parse the tiles of a single chunk
+///
+/// Only the tiles covered by the region are kept.
+/// The ids of the elements around the chunk are collected as well,
+/// so changes to them find the chunk again.
+/// `file` should be the chunk's extract, parsing takes a while anyway,
+/// so this should be run on a blocking thread.
+fn parse_chunk(file: &str, range: TileRange, region: &Region) -> Result<Chunk, String> {
+    let cols = range.max_x - range.min_x;
+    let rows = range.max_y - range.min_y;
+    let size = 1.0 / f64::from(1u32 << ZOOM);
+    let center = world::unproject(Point::new(
+        f64::from(range.min_x + range.max_x) / 2.0 * size,
+        f64::from(range.min_y + range.max_y) / 2.0 * size,
+    ));
 
-pub(crate) async fn parse_osm(
-    db: Database,
-    file: String,
-    cols: usize,
-    rows: usize,
-    center_x: f64,
-    center_y: f64,
-) -> Result<(), String> {
     let osm_tiles = rustymon_world::parse(rustymon_world::Config {
         zoom: ZOOM,
-        center_x,
-        center_y,
-        rows,
-        cols,
-        file,
+        center_x: center.x,
+        center_y: center.y,
+        rows: rows as usize,
+        cols: cols as usize,
+        file: file.to_string(),
         visual: prototyping::Parser::from_file(TAGS_FILE).unwrap(),
         projection: PROJECTION,
     })?;
 
-    let tiles = Vec::from_iter(osm_tiles.iter().map(|tile| TileInsert {
-        min_x: tile.min.x,
-        min_y: tile.min.y,
-        max_x: tile.max.x,
-        max_y: tile.max.y,
-    }));
-    let tiles = insert!(&db, TileInsert)
-        .bulk(&tiles)
+    // Each parsed tile is dropped once it has been converted, to not hold the chunk twice
+    let mut detail = Vec::with_capacity(osm_tiles.len());
+    for tile in osm_tiles {
+        // Use the center to be independent of rounding errors at the tile's edges
+        let center = Point::new(
+            (tile.min.x + tile.max.x) / 2.0,
+            (tile.min.y + tile.max.y) / 2.0,
+        );
+        let address = TileAddress::containing(center, ZOOM);
+        // Tiles outside the chunk belong to a neighbouring one
+        if !range.contains(address) || !region.covers(address) {
+            continue;
+        }
+
+        let mut shapes = PyramidTile::new(address);
+        for area in tile.iter_areas() {
+            shapes.areas.push(Shape {
+                points: area.points.to_vec(),
+                features: area.feature.to_vec(),
+            });
+        }
+        for way in tile.iter_ways() {
+            shapes.ways.push(Shape {
+                points: way.points.to_vec(),
+                features: way.feature.to_vec(),
+            });
+        }
+        let nodes = tile
+            .iter_nodes()
+            .map(|node| (*node.points, node.feature.to_vec()))
+            .collect();
+
+        detail.push(DetailTile {
+            min: tile.min,
+            max: tile.max,
+            nodes,
+            shapes,
+        });
+    }
+
+    let shift = ZOOM - pyramid::MIN_ZOOM;
+    let root = TileAddress {
+        z: pyramid::MIN_ZOOM,
+        x: range.min_x >> shift,
+        y: range.min_y >> shift,
+    };
+    let elements = osm_pbf::elements_in(file, bbox(range))?;
+    Ok(Chunk {
+        root,
+        detail,
+        elements,
+    })
+}
+
+fn tile_id(tile: &ForeignModel<Tile>) -> i64 {
+    match tile {
+        ForeignModel::Key(id) => *id,
+        ForeignModel::Instance(tile) => tile.id,
+    }
+}
+
+/// Load the areas and ways of the active tiles below `root` which aren't `replaced`
+///
+/// The staged coarse tiles replace the active ones,
+/// so they have to contain the active detail tiles which stay as well.
+async fn active_detail(
+    db: &Database,
+    root: TileAddress,
+    replaced: &HashSet<TileAddress>,
+) -> Result<Vec<PyramidTile>, String> {
+    let shift = ZOOM - root.z;
+    let mut tiles: HashMap<i64, PyramidTile> = query!(db, Tile)
+        .condition(and!(
+            Tile::F.active.equals(true),
+            Tile::F.zoom.equals(i16::from(ZOOM)),
+            Tile::F.x.greater_or_equals(i64::from(root.x << shift)),
+            Tile::F.x.less_than(i64::from((root.x + 1) << shift)),
+            Tile::F.y.greater_or_equals(i64::from(root.y << shift)),
+            Tile::F.y.less_than(i64::from((root.y + 1) << shift))
+        ))
+        .all()
         .await
-        .expect("Error while creating tiles");
+        .map_err(|e| format!("Error while reading the active tiles: {e}"))?
+        .iter()
+        .map(|tile| (tile.id, PyramidTile::new(TileAddress::of(tile))))
+        .filter(|(_, tile)| !replaced.contains(&tile.address))
+        .collect();
+
+    let ids: Vec<_> = tiles.keys().copied().collect();
+    for batch in ids.chunks(ID_BATCH) {
+        let areas = query!(db, Area)
+            .condition(DynamicCollection::or(
+                batch.iter().map(|&id| Area::F.tile.equals(id)).collect(),
+            ))
+            .all()
+            .await
+            .map_err(|e| format!("Error while reading the active areas: {e}"))?;
+        for area in areas {
+            match (area.points(), area.features()) {
+                (Ok(points), Ok(features)) => {
+                    if let Some(tile) = tiles.get_mut(&tile_id(&area.tile)) {
+                        tile.areas.push(Shape { points, features });
+                    }
+                }
+                (Err(err), _) | (_, Err(err)) => {
+                    println!("Skipping corrupted area {}: {err}", area.id)
+                }
+            }
+        }
+
+        let ways = query!(db, Way)
+            .condition(DynamicCollection::or(
+                batch.iter().map(|&id| Way::F.tile.equals(id)).collect(),
+            ))
+            .all()
+            .await
+            .map_err(|e| format!("Error while reading the active ways: {e}"))?;
+        for way in ways {
+            match (way.points(), way.features()) {
+                (Ok(points), Ok(features)) => {
+                    if let Some(tile) = tiles.get_mut(&tile_id(&way.tile)) {
+                        tile.ways.push(Shape { points, features });
+                    }
+                }
+                (Err(err), _) | (_, Err(err)) => {
+                    println!("Skipping corrupted way {}: {err}", way.id)
+                }
+            }
+        }
+    }
+    Ok(tiles.into_values().collect())
+}
+
+/// Generate the coarse tiles above a chunk
+///
+/// They are built from the chunk and the active detail tiles below the same
+/// tile at [`MIN_ZOOM`](pyramid::MIN_ZOOM) which the chunk doesn't replace.
+async fn build_pyramid(db: &Database, chunk: Chunk) -> Result<(Chunk, Vec<PyramidTile>), String> {
+    let replaced = chunk
+        .detail
+        .iter()
+        .map(|tile| tile.shapes.address)
+        .collect();
+    let active = active_detail(db, chunk.root, &replaced).await?;
+
+    tokio::task::spawn_blocking(move || {
+        let shapes: Vec<_> = chunk
+            .detail
+            .iter()
+            .map(|tile| &tile.shapes)
+            .chain(active.iter())
+            .collect();
+        let pyramid = pyramid::build(&shapes);
+        (chunk, pyramid)
+    })
+    .await
+    .map_err(|e| format!("Building the coarse tiles panicked: {e}"))
+}
+
+/// Write a chunk's tiles and their features in batches of `batch_size` rows
+///
+/// The tiles are marked as complete once all their features have been written.
+/// Returns the number of written features.
+async fn write_chunk(db: &Database, chunk: Chunk, batch_size: usize) -> Result<usize, String> {
+    let (chunk, mut pyramid) = build_pyramid(db, chunk).await?;
+    // The elements are recorded at the chunk's root, which is replaced along with its pyramid.
+    // Without any tiles at full detail below it there is no root, so an empty one is created.
+    let root = match pyramid.iter().position(|tile| tile.address == chunk.root) {
+        Some(index) => Some(index),
+        None if !chunk.elements.is_empty() => {
+            pyramid.push(PyramidTile::new(chunk.root));
+            Some(pyramid.len() - 1)
+        }
+        None => None,
+    };
+
+    let mut tiles = Vec::with_capacity(chunk.detail.len() + pyramid.len());
+    for tile in &chunk.detail {
+        tiles.push(TileInsert {
+            active: false,
+            complete: false,
+            zoom: i16::from(ZOOM),
+            x: i64::from(tile.shapes.address.x),
+            y: i64::from(tile.shapes.address.y),
+            min_x: tile.min.x,
+            min_y: tile.min.y,
+            max_x: tile.max.x,
+            max_y: tile.max.y,
+        });
+    }
+    for tile in &pyramid {
+        let origin = tile.address.origin();
+        let size = tile.address.size();
+        tiles.push(TileInsert {
+            active: false,
+            complete: false,
+            zoom: i16::from(tile.address.z),
+            x: i64::from(tile.address.x),
+            y: i64::from(tile.address.y),
+            min_x: origin.x,
+            min_y: origin.y,
+            max_x: origin.x + size,
+            max_y: origin.y + size,
+        });
+    }
+
+    let mut ids = Vec::with_capacity(tiles.len());
+    for batch in tiles.chunks(batch_size) {
+        ids.extend(
+            insert!(db, TileInsert)
+                .bulk(batch)
+                .await
+                .map_err(|e| format!("Error while creating tiles: {e}"))?,
+        );
+    }
 
     let mut ways = Vec::new();
     let mut nodes = Vec::new();
     let mut areas = Vec::new();
-    for (tile, &id) in osm_tiles.iter().zip(tiles.iter()) {
-        for area in tile.iter_areas() {
-            areas.push(AreaInsert::new(id, area));
+    let shapes = chunk
+        .detail
+        .iter()
+        .map(|tile| &tile.shapes)
+        .chain(pyramid.iter());
+    for (tile, &id) in shapes.zip(ids.iter()) {
+        for area in &tile.areas {
+            areas.push(AreaInsert::from_parts(id, &area.points, &area.features));
         }
 
-        for node in tile.iter_nodes() {
-            nodes.push(NodeInsert::new(id, node));
+        for way in &tile.ways {
+            ways.push(WayInsert::from_parts(id, &way.points, &way.features));
         }
-
-        for way in tile.iter_ways() {
-            ways.push(WayInsert::new(id, way));
+    }
+    for (tile, &id) in chunk.detail.iter().zip(ids.iter()) {
+        for (point, features) in &tile.nodes {
+            nodes.push(NodeInsert::from_parts(id, *point, features));
         }
     }
 
-    let way_fut = async {
-        insert!(&db, WayInsert)
-            .bulk(&ways)
+    for batch in ways.chunks(batch_size) {
+        insert!(db, WayInsert)
+            .bulk(batch)
             .await
-            .expect("Error while inserting ways");
-    };
+            .map_err(|e| format!("Error while inserting ways: {e}"))?;
+    }
+    for batch in nodes.chunks(batch_size) {
+        insert!(db, NodeInsert)
+            .bulk(batch)
+            .await
+            .map_err(|e| format!("Error while inserting nodes: {e}"))?;
+    }
+    for batch in areas.chunks(batch_size) {
+        insert!(db, AreaInsert)
+            .bulk(batch)
+            .await
+            .map_err(|e| format!("Error while inserting areas: {e}"))?;
+    }
 
-    let node_fut = async {
-        insert!(&db, NodeInsert)
-            .bulk(&nodes)
+    let root = root.map(|index| ids[chunk.detail.len() + index]);
+    let elements: Vec<_> = root
+        .into_iter()
+        .flat_map(|root| {
+            chunk
+                .elements
+                .iter()
+                .map(move |&(kind, osm_id)| TileElementInsert {
+                    tile: ForeignModel::Key(root),
+                    kind,
+                    osm_id,
+                })
+        })
+        .collect();
+    for batch in elements.chunks(batch_size) {
+        insert!(db, TileElementInsert)
+            .bulk(batch)
             .await
-            .expect("Error while inserting ways");
+            .map_err(|e| format!("Error while recording elements: {e}"))?;
+    }
+
+    // Only the tiles of this chunk are incomplete, the previous ones have been marked already
+    update!(db, Tile)
+        .set(Tile::F.complete, true)
+        .condition(and!(
+            Tile::F.active.equals(false),
+            Tile::F.complete.equals(false)
+        ))
+        .exec()
+        .await
+        .map_err(|e| format!("Error while completing tiles: {e}"))?;
+
+    Ok(ways.len() + nodes.len() + areas.len())
+}
+
+/// Delete tiles together with their features
+async fn delete_tiles(
+    db: &Database,
+    tx: &mut Transaction<'_>,
+    ids: &[i64],
+) -> Result<(), rorm::Error> {
+    for batch in ids.chunks(ID_BATCH) {
+        delete!(db, Area)
+            .transaction(tx)
+            .condition(DynamicCollection::or(
+                batch.iter().map(|&id| Area::F.tile.equals(id)).collect(),
+            ))
+            .await?;
+        delete!(db, Way)
+            .transaction(tx)
+            .condition(DynamicCollection::or(
+                batch.iter().map(|&id| Way::F.tile.equals(id)).collect(),
+            ))
+            .await?;
+        delete!(db, Node)
+            .transaction(tx)
+            .condition(DynamicCollection::or(
+                batch.iter().map(|&id| Node::F.tile.equals(id)).collect(),
+            ))
+            .await?;
+        delete!(db, TileElement)
+            .transaction(tx)
+            .condition(DynamicCollection::or(
+                batch
+                    .iter()
+                    .map(|&id| TileElement::F.tile.equals(id))
+                    .collect(),
+            ))
+            .await?;
+        delete!(db, Tile)
+            .transaction(tx)
+            .condition(DynamicCollection::or(
+                batch.iter().map(|&id| Tile::F.id.equals(id)).collect(),
+            ))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Remove the staged tiles of an import which didn't finish
+///
+/// With `keep_complete` only the tiles of the chunk which was interrupted are removed.
+async fn discard_staged(db: &Database, keep_complete: bool) -> Result<(), rorm::Error> {
+    let staged: Vec<_> = query!(db, Tile)
+        .condition(Tile::F.active.equals(false))
+        .all()
+        .await?
+        .into_iter()
+        .filter(|tile| !(keep_complete && tile.complete))
+        .map(|tile| tile.id)
+        .collect();
+    if staged.is_empty() {
+        return Ok(());
+    }
+
+    let mut tx = db.start_transaction().await?;
+    delete_tiles(db, &mut tx, &staged).await?;
+    tx.commit().await
+}
+
+/// Check whether the staged tiles belong to an import of the same file and region
+///
+/// Returns `false` if there is nothing to resume.
+/// Fails if the staged tiles belong to a different import, resuming would mix both.
+async fn resumable(db: &Database, staged: &StagedImportInsert) -> Result<bool, String> {
+    let Some(previous) = query!(db, StagedImport)
+        .optional()
+        .await
+        .map_err(|e| format!("Error while reading the previous import: {e}"))?
+    else {
+        println!("There is no import to resume, starting over");
+        return Ok(false);
     };
 
-    let area_fut = async {
-        insert!(&db, AreaInsert)
-            .bulk(&areas)
-            .await
-            .expect("Error while inserting ways");
+    if previous.file != staged.file {
+        return Err(format!(
+            "The staged tiles were parsed from {}, resume with the same file or start over",
+            previous.file
+        ));
+    }
+    if previous.region != staged.region {
+        return Err(
+            "The staged tiles cover a different region, resume with the same region or start over"
+                .to_string(),
+        );
+    }
+    Ok(true)
+}
+
+/// Record the file and region the staged tiles are parsed from
+async fn record_staged(db: &Database, staged: &StagedImportInsert) -> Result<(), rorm::Error> {
+    let mut tx = db.start_transaction().await?;
+    delete!(db, StagedImport).transaction(&mut tx).all().await?;
+    insert!(db, StagedImportInsert)
+        .transaction(&mut tx)
+        .single(staged)
+        .await?;
+    tx.commit().await
+}
+
+/// Replace the active tiles by the staged ones
+///
+/// Active tiles are only replaced where a staged tile with the same address exists.
+/// The staged coarse tiles contain the active detail tiles below them which stay,
+/// so importing a region keeps the rest of the world at every zoom level.
+/// Everything happens in a single transaction, the server never sees a partial import.
+async fn activate_staged(db: &Database, source: String) -> Result<(), rorm::Error> {
+    let mut tx = db.start_transaction().await?;
+
+    let staged: HashSet<_> = query!(db, Tile)
+        .transaction(&mut tx)
+        .condition(Tile::F.active.equals(false))
+        .all()
+        .await?
+        .iter()
+        .map(TileAddress::of)
+        .collect();
+    let replaced: Vec<_> = query!(db, Tile)
+        .transaction(&mut tx)
+        .condition(Tile::F.active.equals(true))
+        .all()
+        .await?
+        .into_iter()
+        .filter(|tile| staged.contains(&TileAddress::of(tile)))
+        .map(|tile| tile.id)
+        .collect();
+    delete_tiles(db, &mut tx, &replaced).await?;
+
+    update!(db, Tile)
+        .transaction(&mut tx)
+        .set(Tile::F.active, true)
+        .condition(Tile::F.active.equals(false))
+        .exec()
+        .await?;
+    delete!(db, StagedImport).transaction(&mut tx).all().await?;
+    insert!(db, WorldRevisionInsert)
+        .transaction(&mut tx)
+        .single(&WorldRevisionInsert { source })
+        .await?;
+
+    tx.commit().await
+}
+
+/// Options of an import which don't affect the imported data
+pub(crate) struct ImportOptions {
+    /// Number of rows written to the database at once
+    pub(crate) batch_size: usize,
+    /// Continue a previous import of the same file and region instead of starting over
+    pub(crate) resume: bool,
+}
+
+/// Progress of an import, printed after each chunk
+struct Progress {
+    total: u64,
+    done: u64,
+    /// Tiles done by a previous run, they don't count towards the speed
+    resumed: u64,
+    elements: u64,
+    started: Instant,
+}
+
+impl Progress {
+    fn new(total: u64, resumed: u64) -> Self {
+        Self {
+            total,
+            done: resumed,
+            resumed,
+            elements: 0,
+            started: Instant::now(),
+        }
+    }
+
+    fn chunk_done(&mut self, tiles: u64, elements: usize) {
+        self.done += tiles;
+        self.elements += elements as u64;
+
+        let elapsed = self.started.elapsed();
+        let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+        let processed = self.done - self.resumed;
+        let eta =
+            Duration::from_secs_f64(seconds / processed as f64 * (self.total - self.done) as f64);
+        println!(
+            "[{:5.1}%] {}/{} tiles, {:.0} elements/s, ETA {}",
+            self.done as f64 / self.total as f64 * 100.0,
+            self.done,
+            self.total,
+            self.elements as f64 / seconds,
+            format_duration(eta),
+        );
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..=59 => format!("{seconds}s"),
+        60..=3599 => format!("{}m {:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {:02}m", seconds / 3600, seconds / 60 % 60),
+    }
+}
+
+/// Import the tiles of a region from a PBF file
+///
+/// The region is parsed in chunks of the tiles below a tile at [`MIN_ZOOM`](pyramid::MIN_ZOOM).
+/// Chunks without any tile of the region are skipped.
+/// If `osmium` is installed, the chunks are cut out of the file in batches first,
+/// so the file isn't read again for every chunk.
+/// Each chunk is written to the database while the next one is parsed,
+/// so only a few chunks are held in memory at once.
+///
+/// The tiles are staged until the whole region has been written
+/// and then replace the existing tiles at once.
+/// Staged tiles left behind by a failed import are discarded first,
+/// unless the import is resumed, which skips the chunks written completely.
+/// Only an import of the same file and region can be resumed.
+/// `source` is recorded in the world revision created by the import.
+async fn import(
+    db: &Database,
+    file: String,
+    source: String,
+    region: Region,
+    options: ImportOptions,
+) -> Result<(), String> {
+    if options.batch_size == 0 {
+        return Err("The batch size must be greater than zero".to_string());
+    }
+    let mut chunks: Vec<_> = region
+        .range()
+        .chunks()
+        .into_iter()
+        .map(|range| {
+            let tiles = range.addresses().filter(|&a| region.covers(a)).count() as u64;
+            (range, tiles)
+        })
+        .filter(|&(_, tiles)| tiles > 0)
+        .collect();
+    let total = chunks.iter().map(|&(_, tiles)| tiles).sum();
+
+    let staged = StagedImportInsert {
+        // The same file may be passed by another path when resuming
+        file: fs::canonicalize(&file)
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| file.clone()),
+        region: region.fingerprint(),
     };
+    let resume = options.resume && resumable(db, &staged).await?;
 
-    futures::join!(way_fut, node_fut, area_fut);
+    discard_staged(db, resume)
+        .await
+        .map_err(|e| format!("Error while discarding a previous import: {e}"))?;
+    if !resume {
+        record_staged(db, &staged)
+            .await
+            .map_err(|e| format!("Error while recording the import: {e}"))?;
+    }
+
+    let mut resumed = 0;
+    if resume {
+        let complete: Vec<_> = query!(db, Tile)
+            .condition(and!(
+                Tile::F.active.equals(false),
+                Tile::F.zoom.equals(i16::from(ZOOM))
+            ))
+            .all()
+            .await
+            .map_err(|e| format!("Error while reading the previous import: {e}"))?
+            .iter()
+            .map(TileAddress::of)
+            .collect();
+        chunks.retain(|&(range, tiles)| {
+            let done = complete.iter().any(|&address| range.contains(address));
+            if done {
+                resumed += tiles;
+            }
+            !done
+        });
+        println!("Resuming import, {resumed} tiles have been imported already");
+    }
+    let mut progress = Progress::new(total, resumed);
+
+    let extract = osmium_available();
+    if !extract {
+        println!("osmium was not found, every chunk has to read the whole file");
+    }
+
+    let (sender, mut receiver) = mpsc::channel(CHUNK_BUFFER);
+    let parser = tokio::task::spawn_blocking(move || {
+        for (batch, chunks) in chunks.chunks(EXTRACT_BATCH).enumerate() {
+            let extracts = if extract {
+                let ranges: Vec<_> = chunks.iter().map(|&(range, _)| range).collect();
+                Some(Extracts::cut(&file, &ranges, batch)?)
+            } else {
+                None
+            };
+
+            for (index, &(range, tiles)) in chunks.iter().enumerate() {
+                let chunk = match &extracts {
+                    Some(extracts) => parse_chunk(&extracts.file(index), range, &region)?,
+                    None => parse_chunk(&file, range, &region)?,
+                };
+                if sender.blocking_send((tiles, chunk)).is_err() {
+                    // The writer failed and reports its error
+                    return Ok(());
+                }
+            }
+        }
+        Ok::<_, String>(())
+    });
+
+    while let Some((tiles, chunk)) = receiver.recv().await {
+        let elements = write_chunk(db, chunk, options.batch_size).await?;
+        progress.chunk_done(tiles, elements);
+    }
+    parser
+        .await
+        .map_err(|e| format!("The parser panicked: {e}"))??;
+
+    activate_staged(db, source)
+        .await
+        .map_err(|e| format!("Error while activating the import: {e}"))?;
+    println!("Import finished");
 
     Ok(())
 }
+
+/// Import the tiles covered by a region
+pub(crate) async fn parse_osm(
+    db: Database,
+    file: String,
+    region: Region,
+    options: ImportOptions,
+) -> Result<(), String> {
+    let source = file.clone();
+    import(&db, file, source, region, options).await
+}
+
+/// Find the tiles at [`MIN_ZOOM`](pyramid::MIN_ZOOM) which contained elements before a change
+async fn recorded_roots(
+    db: &Database,
+    elements: &[(ElementKind, i64)],
+) -> Result<HashSet<TileAddress>, String> {
+    let recorded = query!(db, TileElement)
+        .optional()
+        .await
+        .map_err(|e| format!("Error while reading the recorded elements: {e}"))?;
+    if recorded.is_none() {
+        println!(
+            "The world was imported without recording its elements, \
+            deleted elements are only found by their position in the change file"
+        );
+        return Ok(HashSet::new());
+    }
+
+    let elements: HashSet<_> = elements.iter().copied().collect();
+    let ids: Vec<_> = elements
+        .iter()
+        .map(|&(_, id)| id)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    let mut tiles = HashSet::new();
+    for batch in ids.chunks(ID_BATCH) {
+        let recorded = query!(db, TileElement)
+            .condition(DynamicCollection::or(
+                batch
+                    .iter()
+                    .map(|&id| TileElement::F.osm_id.equals(id))
+                    .collect(),
+            ))
+            .all()
+            .await
+            .map_err(|e| format!("Error while reading the recorded elements: {e}"))?;
+        tiles.extend(
+            recorded
+                .iter()
+                .filter(|element| elements.contains(&(element.kind, element.osm_id)))
+                .map(|element| tile_id(&element.tile)),
+        );
+    }
+
+    let tiles: Vec<_> = tiles.into_iter().collect();
+    let mut roots = HashSet::new();
+    for batch in tiles.chunks(ID_BATCH) {
+        let active = query!(db, Tile)
+            .condition(and!(
+                Tile::F.active.equals(true),
+                DynamicCollection::or(batch.iter().map(|&id| Tile::F.id.equals(id)).collect())
+            ))
+            .all()
+            .await
+            .map_err(|e| format!("Error while reading the recorded tiles: {e}"))?;
+        roots.extend(active.iter().map(TileAddress::of));
+    }
+    Ok(roots)
+}
+
+/// Update the tiles touched by an OsmChange file
+///
+/// `file` has to contain the data with the change applied, e.g. by `osmium apply-changes`.
+/// It provides the positions of the nodes which the change references but doesn't contain.
+/// Modified and deleted elements are also found in the tiles the import recorded them at,
+/// so their previous tiles are updated as well.
+/// Only tiles which have been imported before are updated, the rest of the change is ignored.
+///
+/// The coarse levels are built from all tiles below a tile at [`MIN_ZOOM`](pyramid::MIN_ZOOM),
+/// so every imported tile sharing that tile with a touched one is parsed again.
+pub(crate) async fn apply_osm_diff(
+    db: Database,
+    file: String,
+    diff: String,
+    options: ImportOptions,
+) -> Result<(), String> {
+    let change = OsmChange::read(&diff)?;
+    let located = {
+        let file = file.clone();
+        tokio::task::spawn_blocking(move || change.locate(&file))
+            .await
+            .map_err(|e| format!("Locating the change panicked: {e}"))??
+    };
+
+    let mut roots: HashSet<_> = located
+        .points
+        .into_iter()
+        .map(|point| TileAddress::containing(point, pyramid::MIN_ZOOM))
+        .collect();
+    roots.extend(recorded_roots(&db, &located.elements).await?);
+    let shift = ZOOM - pyramid::MIN_ZOOM;
+    let tiles = query!(&db, Tile)
+        .condition(and!(
+            Tile::F.active.equals(true),
+            Tile::F.zoom.equals(i16::from(ZOOM))
+        ))
+        .all()
+        .await
+        .map_err(|e| format!("Error while reading the imported tiles: {e}"))?
+        .iter()
+        .map(TileAddress::of)
+        .filter(|address| {
+            roots.contains(&TileAddress {
+                z: pyramid::MIN_ZOOM,
+                x: address.x >> shift,
+                y: address.y >> shift,
+            })
+        })
+        .collect();
+
+    let Some(region) = Region::tiles(tiles) else {
+        println!("The change doesn't touch the imported world");
+        return Ok(());
+    };
+    import(&db, file, diff, region, options).await
+}
@@ -0,0 +1,532 @@
+//! Selection of the tiles an import covers
+
+use std::collections::HashSet;
+use std::fs::read_to_string;
+use std::path::Path;
+
+use rustymon_world::geometry::Point;
+use serde_json::Value;
+
+use crate::world::pyramid::MIN_ZOOM;
+use crate::world::{Coord, TileAddress, ZOOM};
+
+/// Rectangle of tiles at [`ZOOM`], the maximums are exclusive
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct TileRange {
+    pub(crate) min_x: u32,
+    pub(crate) max_x: u32,
+    pub(crate) min_y: u32,
+    pub(crate) max_y: u32,
+}
+
+impl TileRange {
+    /// Get the `cols` x `rows` tiles around the tile containing `center`
+    fn around(center: Point, cols: u32, rows: u32) -> Self {
+        let center = TileAddress::containing(center, ZOOM);
+        let min_x = center.x.saturating_sub(cols / 2);
+        let min_y = center.y.saturating_sub(rows / 2);
+        let tiles = 1 << ZOOM;
+        Self {
+            min_x,
+            max_x: (min_x + cols).min(tiles),
+            min_y,
+            max_y: (min_y + rows).min(tiles),
+        }
+    }
+
+    /// Get the tiles covering the rectangle spanned by two projected points
+    fn spanning(min: Point, max: Point) -> Self {
+        let min = TileAddress::containing(min, ZOOM);
+        let max = TileAddress::containing(max, ZOOM);
+        Self {
+            min_x: min.x,
+            max_x: max.x + 1,
+            min_y: min.y,
+            max_y: max.y + 1,
+        }
+    }
+
+    /// Intersect the range with the tiles below a tile at [`MIN_ZOOM`]
+    fn below(&self, root: TileAddress) -> Option<Self> {
+        let shift = ZOOM - root.z;
+        let range = Self {
+            min_x: self.min_x.max(root.x << shift),
+            max_x: self.max_x.min((root.x + 1) << shift),
+            min_y: self.min_y.max(root.y << shift),
+            max_y: self.max_y.min((root.y + 1) << shift),
+        };
+        (range.min_x < range.max_x && range.min_y < range.max_y).then_some(range)
+    }
+
+    pub(crate) fn contains(&self, address: TileAddress) -> bool {
+        (self.min_x..self.max_x).contains(&address.x)
+            && (self.min_y..self.max_y).contains(&address.y)
+    }
+
+    /// Iterate over the addresses of all tiles in the range
+    pub(crate) fn addresses(&self) -> impl Iterator<Item = TileAddress> + '_ {
+        (self.min_y..self.max_y)
+            .flat_map(move |y| (self.min_x..self.max_x).map(move |x| TileAddress { z: ZOOM, x, y }))
+    }
+
+    /// Split the range into the parts below each tile at [`MIN_ZOOM`]
+    ///
+    /// Each part contains everything needed to build the pyramid above it.
+    pub(crate) fn chunks(&self) -> Vec<Self> {
+        let shift = ZOOM - MIN_ZOOM;
+        let mut chunks = Vec::new();
+        for y in self.min_y >> shift..=(self.max_y - 1) >> shift {
+            for x in self.min_x >> shift..=(self.max_x - 1) >> shift {
+                chunks.extend(self.below(TileAddress { z: MIN_ZOOM, x, y }));
+            }
+        }
+        chunks
+    }
+}
+
+/// The part of the world to import
+pub(crate) enum Region {
+    /// Every tile in a rectangle
+    Rectangle(TileRange),
+    /// Every tile intersecting a polygon
+    ///
+    /// The rings are projected and combined with the even-odd rule,
+    /// so holes are simply rings inside other rings.
+    Polygon {
+        range: TileRange,
+        rings: Vec<Vec<Point>>,
+    },
+    /// An arbitrary set of tiles
+    Tiles {
+        range: TileRange,
+        tiles: HashSet<TileAddress>,
+    },
+}
+
+fn project(lng: f64, lat: f64) -> Result<Point, String> {
+    Coord { lat, lng }
+        .project()
+        .ok_or_else(|| format!("Coordinate out of range: {lng}, {lat}"))
+}
+
+impl Region {
+    /// Select the `cols` x `rows` tiles around a center
+    pub(crate) fn grid(lng: f64, lat: f64, cols: usize, rows: usize) -> Result<Self, String> {
+        let cols = u32::try_from(cols).map_err(|_| "Too many columns".to_string())?;
+        let rows = u32::try_from(rows).map_err(|_| "Too many rows".to_string())?;
+        if cols == 0 || rows == 0 {
+            return Err("At least one column and row are required".to_string());
+        }
+        Ok(Region::Rectangle(TileRange::around(
+            project(lng, lat)?,
+            cols,
+            rows,
+        )))
+    }
+
+    /// Select the tiles covering a bounding box given as `min_lng,min_lat,max_lng,max_lat`
+    pub(crate) fn bounding_box(bbox: &str) -> Result<Self, String> {
+        let values = bbox
+            .split(',')
+            .map(|value| value.trim().parse::<f64>().ok())
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| format!("Invalid bounding box: {bbox}"))?;
+        let &[min_lng, min_lat, max_lng, max_lat] = values.as_slice() else {
+            return Err("The bounding box needs four values".to_string());
+        };
+        if min_lng > max_lng || min_lat > max_lat {
+            return Err("The bounding box's minimum exceeds its maximum".to_string());
+        }
+
+        // The projection's y axis points south, so the northern edge becomes the minimum
+        Ok(Region::Rectangle(TileRange::spanning(
+            project(min_lng, max_lat)?,
+            project(max_lng, min_lat)?,
+        )))
+    }
+
+    /// Select the tiles intersecting the polygon in a `.poly` or GeoJSON file
+    pub(crate) fn boundary_file(path: &str) -> Result<Self, String> {
+        let content =
+            read_to_string(path).map_err(|e| format!("Could not read boundary file: {e}"))?;
+        let rings = match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("poly") => parse_poly(&content)?,
+            _ => parse_geojson(&content)?,
+        };
+
+        let rings = rings
+            .into_iter()
+            .map(|ring| {
+                ring.into_iter()
+                    .map(|[lng, lat]| project(lng, lat))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::polygon(rings)
+    }
+
+    fn polygon(rings: Vec<Vec<Point>>) -> Result<Self, String> {
+        let mut min = Point::new(f64::INFINITY, f64::INFINITY);
+        let mut max = Point::new(f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for point in rings.iter().flatten() {
+            min = Point::new(min.x.min(point.x), min.y.min(point.y));
+            max = Point::new(max.x.max(point.x), max.y.max(point.y));
+        }
+        if rings.iter().all(|ring| ring.len() < 3) {
+            return Err("The boundary doesn't contain a polygon".to_string());
+        }
+
+        Ok(Region::Polygon {
+            range: TileRange::spanning(min, max),
+            rings,
+        })
+    }
+
+    /// Select a set of tiles at [`ZOOM`]
+    ///
+    /// Returns `None` if the set is empty.
+    pub(crate) fn tiles(tiles: HashSet<TileAddress>) -> Option<Self> {
+        let range = TileRange {
+            min_x: tiles.iter().map(|tile| tile.x).min()?,
+            max_x: tiles.iter().map(|tile| tile.x).max()? + 1,
+            min_y: tiles.iter().map(|tile| tile.y).min()?,
+            max_y: tiles.iter().map(|tile| tile.y).max()? + 1,
+        };
+        Some(Region::Tiles { range, tiles })
+    }
+
+    /// Get the rectangle of tiles around the region
+    pub(crate) fn range(&self) -> TileRange {
+        match self {
+            Region::Rectangle(range) => *range,
+            Region::Polygon { range, .. } => *range,
+            Region::Tiles { range, .. } => *range,
+        }
+    }
+
+    /// Describe the region, so staged tiles can be matched to it when resuming an import
+    ///
+    /// Polygons and tile sets are hashed with a hash which is stable across runs.
+    pub(crate) fn fingerprint(&self) -> String {
+        let TileRange {
+            min_x,
+            max_x,
+            min_y,
+            max_y,
+        } = self.range();
+        let range = format!("{min_x}-{max_x} {min_y}-{max_y}");
+        match self {
+            Region::Rectangle(_) => format!("rectangle {range}"),
+            Region::Polygon { rings, .. } => {
+                let hash = fnv1a(rings.iter().flat_map(|ring| {
+                    std::iter::once(ring.len() as u64).chain(
+                        ring.iter()
+                            .flat_map(|point| [point.x.to_bits(), point.y.to_bits()]),
+                    )
+                }));
+                format!("polygon {range} {hash:016x}")
+            }
+            Region::Tiles { tiles, .. } => {
+                let mut tiles: Vec<_> = tiles
+                    .iter()
+                    .map(|tile| u64::from(tile.x) << 32 | u64::from(tile.y))
+                    .collect();
+                tiles.sort_unstable();
+                format!("tiles {range} {:016x}", fnv1a(tiles))
+            }
+        }
+    }
+
+    /// Check whether a tile at [`ZOOM`] is part of the region
+    pub(crate) fn covers(&self, address: TileAddress) -> bool {
+        match self {
+            Region::Rectangle(range) => range.contains(address),
+            Region::Polygon { range, rings } => {
+                range.contains(address) && intersects_rectangle(rings, address)
+            }
+            Region::Tiles { tiles, .. } => tiles.contains(&address),
+        }
+    }
+}
+
+/// 64 bit FNV-1a hash of a sequence of numbers
+fn fnv1a(values: impl IntoIterator<Item = u64>) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in values.into_iter().flat_map(u64::to_le_bytes) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Check whether the polygon intersects a tile
+fn intersects_rectangle(rings: &[Vec<Point>], address: TileAddress) -> bool {
+    let min = address.origin();
+    let size = address.size();
+    let max = Point::new(min.x + size, min.y + size);
+
+    // Either the tile is inside the polygon, or an edge crosses the tile
+    contains(rings, min)
+        || rings.iter().any(|ring| {
+            ring.iter()
+                .zip(ring.iter().cycle().skip(1))
+                .any(|(&start, &end)| segment_intersects_rectangle(start, end, min, max))
+        })
+}
+
+/// Even-odd point in polygon test
+fn contains(rings: &[Vec<Point>], point: Point) -> bool {
+    let mut inside = false;
+    for ring in rings {
+        for (a, b) in ring.iter().zip(ring.iter().cycle().skip(1)) {
+            if (a.y > point.y) != (b.y > point.y)
+                && point.x < (b.x - a.x) * (point.y - a.y) / (b.y - a.y) + a.x
+            {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+/// Liang-Barsky clipping of a segment against a rectangle
+fn segment_intersects_rectangle(start: Point, end: Point, min: Point, max: Point) -> bool {
+    let delta = end - start;
+    let mut t0: f64 = 0.0;
+    let mut t1: f64 = 1.0;
+    for (p, q) in [
+        (-delta.x, start.x - min.x),
+        (delta.x, max.x - start.x),
+        (-delta.y, start.y - min.y),
+        (delta.y, max.y - start.y),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return false;
+            }
+        } else {
+            let t = q / p;
+            if p < 0.0 {
+                t0 = t0.max(t);
+            } else {
+                t1 = t1.min(t);
+            }
+        }
+    }
+    t0 <= t1
+}
+
+/// Parse the rings of an osmosis polygon filter file
+///
+/// See <https://wiki.openstreetmap.org/wiki/Osmosis/Polygon_Filter_File_Format>
+fn parse_poly(content: &str) -> Result<Vec<Vec<[f64; 2]>>, String> {
+    let mut lines = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+    // The first line is the name of the polygon
+    lines
+        .next()
+        .ok_or_else(|| "Empty polygon file".to_string())?;
+
+    let mut rings = Vec::new();
+    let mut ring: Option<Vec<[f64; 2]>> = None;
+    for line in lines {
+        match ring.take() {
+            None if line == "END" => return Ok(rings),
+            // Section names, holes start with a `!` but are handled by the even-odd rule
+            None => ring = Some(Vec::new()),
+            Some(points) if line == "END" => rings.push(points),
+            Some(mut points) => {
+                let mut values = line.split_whitespace().map(str::parse::<f64>);
+                match (values.next(), values.next()) {
+                    (Some(Ok(lng)), Some(Ok(lat))) => points.push([lng, lat]),
+                    _ => return Err(format!("Invalid coordinate in polygon file: {line}")),
+                }
+                ring = Some(points);
+            }
+        }
+    }
+    Err("The polygon file isn't terminated by END".to_string())
+}
+
+/// Parse the rings of every polygon in a GeoJSON document
+///
+/// Geometries, features and feature collections of polygons and multi polygons are supported.
+fn parse_geojson(content: &str) -> Result<Vec<Vec<[f64; 2]>>, String> {
+    fn ring(value: &Value) -> Option<Vec<[f64; 2]>> {
+        value
+            .as_array()?
+            .iter()
+            .map(|position| Some([position.get(0)?.as_f64()?, position.get(1)?.as_f64()?]))
+            .collect()
+    }
+    fn polygon(value: &Value) -> Option<Vec<Vec<[f64; 2]>>> {
+        value.as_array()?.iter().map(ring).collect()
+    }
+    fn collect(value: &Value, rings: &mut Vec<Vec<[f64; 2]>>) -> Option<()> {
+        match value.get("type")?.as_str()? {
+            "FeatureCollection" => {
+                for feature in value.get("features")?.as_array()? {
+                    collect(feature, rings)?;
+                }
+            }
+            "Feature" => collect(value.get("geometry")?, rings)?,
+            "Polygon" => rings.extend(polygon(value.get("coordinates")?)?),
+            "MultiPolygon" => {
+                for coordinates in value.get("coordinates")?.as_array()? {
+                    rings.extend(polygon(coordinates)?);
+                }
+            }
+            // Other geometries don't enclose anything
+            _ => {}
+        }
+        Some(())
+    }
+
+    let value: Value =
+        serde_json::from_str(content).map_err(|e| format!("Invalid GeoJSON: {e}"))?;
+    let mut rings = Vec::new();
+    collect(&value, &mut rings).ok_or_else(|| "Malformed GeoJSON geometry".to_string())?;
+    Ok(rings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn poly_files_are_parsed() {
+        let rings = parse_poly(
+            "example
+            1
+                1.0  2.0
+                3.0  2.0
+                3.0  4.0
+            END
+            !2
+                1.5  2.5
+                2.5  2.5
+                2.5  3.5
+            END
+            END",
+        )
+        .unwrap();
+
+        assert_eq!(
+            rings,
+            [
+                vec![[1.0, 2.0], [3.0, 2.0], [3.0, 4.0]],
+                vec![[1.5, 2.5], [2.5, 2.5], [2.5, 3.5]],
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_poly_files_are_rejected() {
+        assert!(parse_poly("").is_err());
+        assert!(parse_poly("example\n1\n1.0 north\nEND\nEND\n").is_err());
+        assert!(parse_poly("example\n1\n1.0 2.0\nEND\n").is_err());
+    }
+
+    #[test]
+    fn geojson_polygons_are_collected() {
+        let rings = parse_geojson(
+            r#"{
+                "type": "FeatureCollection",
+                "features": [
+                    {
+                        "type": "Feature",
+                        "geometry": {
+                            "type": "Polygon",
+                            "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 0]]]
+                        }
+                    },
+                    {
+                        "type": "Feature",
+                        "geometry": {
+                            "type": "MultiPolygon",
+                            "coordinates": [
+                                [[[2, 2], [3, 2], [3, 3], [2, 2]]],
+                                [[[4, 4], [5, 4], [5, 5], [4, 4]]]
+                            ]
+                        }
+                    },
+                    {
+                        "type": "Feature",
+                        "geometry": {"type": "Point", "coordinates": [6, 6]}
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(rings.len(), 3);
+        assert_eq!(rings[0], [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 0.0]]);
+        assert_eq!(rings[2][1], [5.0, 4.0]);
+    }
+
+    #[test]
+    fn malformed_geojson_is_rejected() {
+        assert!(parse_geojson("not json").is_err());
+        assert!(parse_geojson(r#"{"type": "Polygon", "coordinates": [[[0, "a"]]]}"#).is_err());
+    }
+
+    #[test]
+    fn fingerprints_differ_between_regions() {
+        let ring = |offset: f64| {
+            vec![
+                Point::new(0.25, 0.25),
+                Point::new(0.5 + offset, 0.25),
+                Point::new(0.5, 0.5),
+            ]
+        };
+        let polygon = |offset| Region::polygon(vec![ring(offset)]).unwrap().fingerprint();
+
+        assert_eq!(polygon(0.0), polygon(0.0));
+        assert_ne!(polygon(0.0), polygon(1e-9));
+        assert_ne!(
+            Region::bounding_box("0,0,1,1").unwrap().fingerprint(),
+            Region::bounding_box("0,0,1,2").unwrap().fingerprint()
+        );
+
+        let tiles = |x| {
+            Region::tiles(HashSet::from([
+                TileAddress { z: ZOOM, x, y: 1 },
+                TileAddress {
+                    z: ZOOM,
+                    x: 2,
+                    y: 1,
+                },
+            ]))
+            .unwrap()
+            .fingerprint()
+        };
+        assert_eq!(tiles(1), tiles(1));
+        assert_ne!(tiles(1), tiles(3));
+    }
+
+    #[test]
+    fn segments_intersecting_rectangles() {
+        let min = Point::new(0.0, 0.0);
+        let max = Point::new(1.0, 1.0);
+        let intersects = |start: (f64, f64), end: (f64, f64)| {
+            segment_intersects_rectangle(
+                Point::new(start.0, start.1),
+                Point::new(end.0, end.1),
+                min,
+                max,
+            )
+        };
+
+        // Inside, crossing and touching an edge
+        assert!(intersects((0.25, 0.25), (0.75, 0.75)));
+        assert!(intersects((-1.0, 0.5), (2.0, 0.5)));
+        assert!(intersects((-1.0, -1.0), (0.5, 0.5)));
+        assert!(intersects((1.0, -1.0), (1.0, 2.0)));
+        // Beside the rectangle and passing a corner
+        assert!(!intersects((2.0, 0.0), (2.0, 1.0)));
+        assert!(!intersects((-1.0, 0.5), (-0.5, 0.5)));
+        assert!(!intersects((0.5, -1.0), (2.0, 0.5)));
+    }
+}
@@ -4,16 +4,20 @@ use actix_toolbox::tb_middleware::{
 use actix_web::cookie::time::Duration;
 use actix_web::cookie::Key;
 use actix_web::middleware::Compress;
-use actix_web::web::{get, post, scope, Data, JsonConfig, PayloadConfig};
+use actix_web::web::{delete, get, patch, post, scope, Data, JsonConfig, PayloadConfig};
 use actix_web::{App, HttpServer};
 use base64::prelude::BASE64_STANDARD;
 use base64::Engine;
 use rorm::Database;
 
-use crate::handler::{frontend, world};
-use crate::helper::AuthenticationRequired;
+use crate::handler::{admin, frontend, world};
+use crate::helper::sessions::SESSION_TTL;
+use crate::helper::{
+    AuthenticationRequired, DisplayNamePolicy, LoginThrottle, PasswordHashing, RoleRequired,
+};
 use crate::models::config::Config;
-use crate::world::OSMTags;
+use crate::models::db::Role;
+use crate::world::{DistanceThresholds, OSMTags, SharedWorldIndex, TileCache, WorldIndex};
 
 pub(crate) async fn start_server(db: Database, config: Config) -> Result<(), String> {
     let key = match BASE64_STANDARD.decode(config.server.secret_key) {
@@ -32,6 +36,23 @@ pub(crate) async fn start_server(db: Database, config: Config) -> Result<(), Str
     };
 
     let tags_lookup = Data::new(OSMTags::new());
+    let distance_thresholds = Data::new(DistanceThresholds::new(&config.world, &tags_lookup)?);
+    let world_index = Data::new(SharedWorldIndex::new(
+        WorldIndex::load(&db)
+            .await
+            .map_err(|e| format!("Could not load the world: {e}"))?,
+    ));
+    {
+        let world_index = world_index.clone();
+        let db = db.clone();
+        let interval = std::time::Duration::from_secs(config.world.revision_check_interval);
+        tokio::spawn(async move { world_index.watch(db, interval).await });
+    }
+    let login_throttle = Data::new(LoginThrottle::new(config.login_protection));
+    let password_hashing = Data::new(PasswordHashing::new(&config.argon2)?);
+    let display_names = Data::new(DisplayNamePolicy::new(&config.display_name));
+    let tile_cache = Data::new(TileCache::new(&config.world)?);
+    let world_config = Data::new(config.world);
 
     HttpServer::new(move || {
         App::new()
@@ -40,22 +61,57 @@ pub(crate) async fn start_server(db: Database, config: Config) -> Result<(), Str
                     .cookie_secure(false)
                     .session_lifecycle(PersistentSession::session_ttl(
                         PersistentSession::default(),
-                        Duration::hours(1),
+                        Duration::seconds(SESSION_TTL),
                     ))
                     .build(),
             )
             .wrap(Compress::default())
             .wrap(setup_logging_mw(LoggingMiddlewareConfig::default()))
             .app_data(tags_lookup.clone())
+            .app_data(world_index.clone())
+            .app_data(distance_thresholds.clone())
+            .app_data(login_throttle.clone())
+            .app_data(password_hashing.clone())
+            .app_data(display_names.clone())
+            .app_data(world_config.clone())
+            .app_data(tile_cache.clone())
             .app_data(JsonConfig::default())
             .app_data(PayloadConfig::default())
             .app_data(Data::new(db.clone()))
             .route("/api/world/v1/getOsmTags", get().to(world::get_osm_tags))
+            .route("/api/world/v1/features", get().to(world::get_features))
+            .route("/api/world/v1/nearby", get().to(world::get_nearby))
+            .route("/tiles/{z}/{x}/{y}.mvt", get().to(world::get_tile))
             .route("/api/frontend/v1/login", post().to(frontend::login))
+            .route(
+                "/api/frontend/v1/login/second-factor",
+                post().to(frontend::login_second_factor),
+            )
+            .route("/api/frontend/v1/register", post().to(frontend::register))
             .service(
                 scope("/api/frontend/v1")
                     .wrap(AuthenticationRequired)
-                    .route("logout", get().to(frontend::logout)),
+                    .route("logout", get().to(frontend::logout))
+                    .route("change-password", post().to(frontend::change_password))
+                    .route("delete-account", post().to(frontend::delete_account))
+                    .route("totp/enroll", post().to(frontend::totp_enroll))
+                    .route("totp/confirm", post().to(frontend::totp_confirm))
+                    .route("totp/disable", post().to(frontend::totp_disable))
+                    .route("tokens", get().to(frontend::list_api_tokens))
+                    .route("tokens", post().to(frontend::create_api_token))
+                    .route("tokens/{id}", delete().to(frontend::revoke_api_token))
+                    .route("sessions", get().to(frontend::list_sessions))
+                    .route("sessions", delete().to(frontend::revoke_all_sessions))
+                    .route("sessions/{id}", delete().to(frontend::revoke_session))
+                    .route("me", get().to(frontend::get_me))
+                    .route("me", patch().to(frontend::update_me)),
+            )
+            .service(
+                scope("/api/admin/v1")
+                    .wrap(RoleRequired(Role::Admin))
+                    .wrap(AuthenticationRequired)
+                    .route("set-role", post().to(admin::set_role))
+                    .route("world-stats", get().to(admin::world_stats)),
             )
     })
     .bind((
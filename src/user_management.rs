@@ -0,0 +1,115 @@
+use std::io::{stdin, Write};
+
+use rorm::{delete, insert, query, Database, Model};
+
+use crate::helper::{replace_password, valid_username, DisplayNamePolicy, PasswordHashing};
+use crate::models::db::{Role, User, UserInsert};
+
+/// Read a password from stdin
+///
+/// Only the first line is used, so the password can be piped in
+/// without ending up in the shell history.
+fn read_password() -> Result<String, String> {
+    eprint!("Password: ");
+    std::io::stderr()
+        .flush()
+        .map_err(|e| format!("Could not write to stderr: {e}"))?;
+
+    let mut password = String::new();
+    stdin()
+        .read_line(&mut password)
+        .map_err(|e| format!("Could not read password from stdin: {e}"))?;
+    let password = password.trim_end_matches(['\r', '\n']).to_string();
+
+    if password.is_empty() {
+        return Err("Password must not be empty".to_string());
+    }
+    Ok(password)
+}
+
+async fn user_exists(db: &Database, username: &str) -> Result<bool, String> {
+    query!(db, User)
+        .condition(User::F.username.equals(username))
+        .optional()
+        .await
+        .map(|user| user.is_some())
+        .map_err(|e| format!("Database error: {e}"))
+}
+
+pub(crate) async fn create_user(
+    db: Database,
+    hashing: PasswordHashing,
+    display_names: DisplayNamePolicy,
+    username: String,
+    display_name: Option<String>,
+    role: Role,
+) -> Result<(), String> {
+    if !valid_username(&username) {
+        return Err(format!("Invalid username: {username}"));
+    }
+    // Operators chose the username, so falling back to it keeps working for names
+    // which are valid usernames but too short for the display name policy
+    let display_name = match display_name {
+        Some(display_name) => display_names
+            .normalize(&display_name)
+            .ok_or_else(|| format!("Invalid display name: {display_name}"))?,
+        None => username.clone(),
+    };
+    if user_exists(&db, &username).await? {
+        return Err(format!("User {username} already exists"));
+    }
+
+    let password = read_password()?;
+    let password_hash = hashing
+        .hash(&password)
+        .map_err(|e| format!("Could not hash password: {e}"))?;
+
+    insert!(&db, UserInsert)
+        .single(&UserInsert {
+            username: username.clone(),
+            display_name,
+            password_hash,
+            role,
+        })
+        .await
+        .map_err(|e| format!("Could not create user: {e}"))?;
+
+    println!("Created user {username} with role {role:?}");
+    Ok(())
+}
+
+pub(crate) async fn delete_user(db: Database, username: String) -> Result<(), String> {
+    if !user_exists(&db, &username).await? {
+        return Err(format!("User {username} does not exist"));
+    }
+
+    delete!(&db, User)
+        .condition(User::F.username.equals(&username))
+        .await
+        .map_err(|e| format!("Could not delete user: {e}"))?;
+
+    println!("Deleted user {username}");
+    Ok(())
+}
+
+pub(crate) async fn reset_password(
+    db: Database,
+    hashing: PasswordHashing,
+    username: String,
+) -> Result<(), String> {
+    if !user_exists(&db, &username).await? {
+        return Err(format!("User {username} does not exist"));
+    }
+
+    let password = read_password()?;
+    let password_hash = hashing
+        .hash(&password)
+        .map_err(|e| format!("Could not hash password: {e}"))?;
+
+    replace_password(&db, &username, &password_hash, None)
+        .await
+        .map_err(|e| format!("Could not update password: {e}"))?;
+
+    println!("Reset password of user {username}, their sessions and api tokens were revoked");
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::info;
+use lru::LruCache;
+use rorm::Database;
+use serde::Serialize;
+
+use crate::models::config::WorldConfig;
+use crate::world::{latest_revision, tiles};
+use crate::world::{LoadError, MapFeature};
+
+/// Decoded features of a tile and the revision they were loaded in
+type CacheEntry = (Option<i64>, Arc<[MapFeature]>);
+
+struct CacheState {
+    tiles: LruCache<i64, CacheEntry>,
+    /// Latest import the cached tiles belong to
+    revision: Option<i64>,
+    last_check: Option<Instant>,
+}
+
+/// Counters of a [`TileCache`]
+#[derive(Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub capacity: usize,
+    pub revision: Option<i64>,
+}
+
+/// Bounded cache of decoded tiles
+///
+/// The least recently used tile is evicted once the cache is full.
+/// Every import records a [`WorldRevision`](crate::models::db::WorldRevision),
+/// which is checked periodically to drop all tiles once the world changed.
+/// Tiles loaded while the revision changed aren't cached.
+pub struct TileCache {
+    state: Mutex<CacheState>,
+    check_interval: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl TileCache {
+    pub(crate) fn new(config: &WorldConfig) -> Result<Self, String> {
+        let capacity = NonZeroUsize::new(config.tile_cache_size)
+            .ok_or_else(|| "TileCacheSize must be greater than zero".to_string())?;
+
+        Ok(Self {
+            state: Mutex::new(CacheState {
+                tiles: LruCache::new(capacity),
+                revision: None,
+                last_check: None,
+            }),
+            check_interval: Duration::from_secs(config.revision_check_interval),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        self.state.lock().expect("Tile cache mutex got poisoned")
+    }
+
+    /// Clear the cache if the world has been re-imported since the last check
+    async fn check_revision(&self, db: &Database) -> Result<(), rorm::Error> {
+        {
+            let mut state = self.lock();
+            let now = Instant::now();
+            if state
+                .last_check
+                .is_some_and(|last| now - last < self.check_interval)
+            {
+                return Ok(());
+            }
+            state.last_check = Some(now);
+        }
+
+        let latest = latest_revision(db).await?;
+
+        let mut state = self.lock();
+        if state.revision != latest {
+            if state.revision.is_some() {
+                info!("The world has been re-imported, clearing the tile cache");
+            }
+            state.tiles.clear();
+            state.revision = latest;
+        }
+        Ok(())
+    }
+
+    /// Get the decoded features of a tile, loading them on a miss
+    pub async fn load(&self, db: &Database, tile: i64) -> Result<Arc<[MapFeature]>, LoadError> {
+        self.check_revision(db).await?;
+
+        let revision = {
+            let mut state = self.lock();
+            let revision = state.revision;
+            if let Some((cached, features)) = state.tiles.get(&tile) {
+                if *cached == revision {
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    return Ok(features.clone());
+                }
+            }
+            revision
+        };
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let features: Arc<[MapFeature]> = tiles::load_tile(db, tile).await?.into();
+        let mut state = self.lock();
+        // The cache has been cleared while loading, the tile may belong to the old world
+        if state.revision == revision {
+            state.tiles.put(tile, (revision, features.clone()));
+        }
+        Ok(features)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let state = self.lock();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: state.tiles.len(),
+            capacity: state.tiles.cap().get(),
+            revision: state.revision,
+        }
+    }
+}
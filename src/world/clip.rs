@@ -0,0 +1,222 @@
+//! Cutting geometry to an axis aligned rectangle
+//!
+//! Points computed on the rectangle's edges lie exactly on them,
+//! so pieces cut from both sides of an edge meet at the same coordinates.
+
+use rustymon_world::geometry::Point;
+
+/// One edge of the rectangle: the axis it is perpendicular to,
+/// its position and whether the inside has greater values
+type Side = (usize, f64, bool);
+
+fn sides(min: Point, max: Point) -> [Side; 4] {
+    [
+        (0, min.x, true),
+        (0, max.x, false),
+        (1, min.y, true),
+        (1, max.y, false),
+    ]
+}
+
+fn inside(point: Point, (axis, bound, greater): Side) -> bool {
+    if greater {
+        point[axis] >= bound
+    } else {
+        point[axis] <= bound
+    }
+}
+
+/// Point where the segment from `a` to `b` crosses a side
+fn crossing(a: Point, b: Point, (axis, bound, _): Side) -> Point {
+    let t = (bound - a[axis]) / (b[axis] - a[axis]);
+    let mut point = a + (b - a) * t;
+    point[axis] = bound;
+    point
+}
+
+/// Cut a ring to the rectangle spanned by `min` and `max`
+///
+/// Uses the Sutherland-Hodgman algorithm, parts of a concave ring which are
+/// connected outside of the rectangle stay connected along its edge.
+/// The result is empty if the ring doesn't intersect the rectangle.
+pub fn clip_ring(ring: &[Point], min: Point, max: Point) -> Vec<Point> {
+    let mut output = ring.to_vec();
+    if output.len() > 1 && output.first() == output.last() {
+        output.pop();
+    }
+
+    for side in sides(min, max) {
+        let input = std::mem::take(&mut output);
+        let Some(&last) = input.last() else {
+            break;
+        };
+        let mut previous = last;
+        for &point in &input {
+            match (inside(previous, side), inside(point, side)) {
+                (true, true) => output.push(point),
+                (true, false) => output.push(crossing(previous, point, side)),
+                (false, true) => {
+                    output.push(crossing(previous, point, side));
+                    output.push(point);
+                }
+                (false, false) => {}
+            }
+            previous = point;
+        }
+    }
+    output
+}
+
+/// Cut the segment from `a` to `b` to the rectangle with the Liang-Barsky algorithm
+fn clip_segment(a: Point, b: Point, min: Point, max: Point) -> Option<(Point, Point)> {
+    let delta = b - a;
+    let (mut enter, mut exit) = (0.0f64, 1.0f64);
+    for (p, q) in [
+        (-delta.x, a.x - min.x),
+        (delta.x, max.x - a.x),
+        (-delta.y, a.y - min.y),
+        (delta.y, max.y - a.y),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            enter = enter.max(q / p);
+        } else {
+            exit = exit.min(q / p);
+        }
+    }
+    if enter > exit {
+        return None;
+    }
+
+    let clamp = |point: Point| Point::new(point.x.clamp(min.x, max.x), point.y.clamp(min.y, max.y));
+    let start = if enter > 0.0 {
+        clamp(a + delta * enter)
+    } else {
+        a
+    };
+    let end = if exit < 1.0 {
+        clamp(a + delta * exit)
+    } else {
+        b
+    };
+    Some((start, end))
+}
+
+/// Cut a line to the rectangle spanned by `min` and `max`
+///
+/// A line leaving and entering the rectangle again is split into several parts.
+pub fn clip_line(points: &[Point], min: Point, max: Point) -> Vec<Vec<Point>> {
+    let mut parts = Vec::new();
+    let mut current: Vec<Point> = Vec::new();
+    for segment in points.windows(2) {
+        let Some((start, end)) = clip_segment(segment[0], segment[1], min, max) else {
+            continue;
+        };
+        if current.last() != Some(&start) {
+            if current.len() > 1 {
+                parts.push(std::mem::take(&mut current));
+            }
+            current = vec![start];
+        }
+        current.push(end);
+    }
+    if current.len() > 1 {
+        parts.push(current);
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(points: &[(f64, f64)]) -> Vec<Point> {
+        points.iter().map(|&(x, y)| Point::new(x, y)).collect()
+    }
+
+    fn bounds() -> (Point, Point) {
+        (Point::new(0.0, 0.0), Point::new(1.0, 1.0))
+    }
+
+    #[test]
+    fn rings_are_cut_at_the_edges() {
+        let (min, max) = bounds();
+        let clipped = clip_ring(
+            &points(&[(0.5, -0.25), (1.25, 0.5), (0.5, 1.25), (-0.25, 0.5)]),
+            min,
+            max,
+        );
+
+        assert_eq!(clipped.len(), 8);
+        for point in &clipped {
+            assert!((0.0..=1.0).contains(&point.x) && (0.0..=1.0).contains(&point.y));
+        }
+        assert!(clipped.contains(&Point::new(0.75, 0.0)));
+        assert!(clipped.contains(&Point::new(1.0, 0.25)));
+    }
+
+    #[test]
+    fn rings_covering_the_rectangle_become_the_rectangle() {
+        let (min, max) = bounds();
+        let mut clipped = clip_ring(
+            &points(&[
+                (-1.0, -1.0),
+                (2.0, -1.0),
+                (2.0, 2.0),
+                (-1.0, 2.0),
+                (-1.0, -1.0),
+            ]),
+            min,
+            max,
+        );
+
+        clipped.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+        assert_eq!(
+            clipped,
+            points(&[(0.0, 0.0), (0.0, 1.0), (1.0, 0.0), (1.0, 1.0)])
+        );
+    }
+
+    #[test]
+    fn rings_outside_are_dropped() {
+        let (min, max) = bounds();
+        let clipped = clip_ring(&points(&[(2.0, 2.0), (3.0, 2.0), (3.0, 3.0)]), min, max);
+
+        assert!(clipped.is_empty());
+    }
+
+    #[test]
+    fn lines_are_split_where_they_leave() {
+        let (min, max) = bounds();
+        let parts = clip_line(
+            &points(&[
+                (-1.0, 0.5),
+                (0.5, 0.5),
+                (0.5, 2.0),
+                (0.75, 2.0),
+                (0.75, 0.25),
+            ]),
+            min,
+            max,
+        );
+
+        assert_eq!(
+            parts,
+            [
+                points(&[(0.0, 0.5), (0.5, 0.5), (0.5, 1.0)]),
+                points(&[(0.75, 1.0), (0.75, 0.25)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_outside_are_dropped() {
+        let (min, max) = bounds();
+        let parts = clip_line(&points(&[(2.0, 0.5), (3.0, 0.5)]), min, max);
+
+        assert!(parts.is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+use rstar::{PointDistance, RTreeObject, AABB};
+use rustymon_world::geometry::{polygon, polyline, Point};
+use serde::Serialize;
+
+use crate::models::db::{Area, Node, Way};
+use crate::world::LoadError;
+
+/// Type of geometry a [`MapFeature`] has
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeatureKind {
+    Area,
+    Way,
+    Node,
+}
+
+/// A single decoded area, way or node
+pub struct MapFeature {
+    /// Id of the row in the table of its kind
+    pub id: i64,
+    pub kind: FeatureKind,
+    /// The geometry's points, a single one for nodes
+    pub points: Box<[Point]>,
+    pub features: Box<[[u32; 2]]>,
+    envelope: AABB<[f64; 2]>,
+}
+
+impl MapFeature {
+    pub fn new(
+        id: i64,
+        kind: FeatureKind,
+        points: Box<[Point]>,
+        features: Box<[[u32; 2]]>,
+    ) -> Self {
+        let mut min = [f64::INFINITY; 2];
+        let mut max = [f64::NEG_INFINITY; 2];
+        for point in points.iter() {
+            min = [min[0].min(point.x), min[1].min(point.y)];
+            max = [max[0].max(point.x), max[1].max(point.y)];
+        }
+
+        Self {
+            id,
+            kind,
+            points,
+            features,
+            envelope: AABB::from_corners(min, max),
+        }
+    }
+
+    /// Projected distance between the feature and a point
+    ///
+    /// Points inside an area have a distance of zero.
+    pub fn distance_to(&self, point: Point) -> f64 {
+        match self.kind {
+            FeatureKind::Area if polygon::contains_point(&self.points, point) => 0.0,
+            FeatureKind::Area => {
+                let mut ring = self.points.to_vec();
+                if let Some(&first) = ring.first() {
+                    ring.push(first);
+                }
+                polyline::distance_to(&ring, point)
+            }
+            FeatureKind::Way => polyline::distance_to(&self.points, point),
+            FeatureKind::Node => point.metric_distance(&self.points[0]),
+        }
+    }
+
+    /// Check whether the feature has any of the given tags
+    ///
+    /// A filter without a value matches every value of its key.
+    pub fn matches(&self, filter: &[(u32, Option<u32>)]) -> bool {
+        self.features.iter().any(|&[key, value]| {
+            filter
+                .iter()
+                .any(|&(k, v)| k == key && v.map_or(true, |v| v == value))
+        })
+    }
+}
+
+impl RTreeObject for MapFeature {
+    type Envelope = AABB<[f64; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        self.envelope
+    }
+}
+
+impl PointDistance for MapFeature {
+    fn distance_2(&self, point: &[f64; 2]) -> f64 {
+        self.distance_to(Point::new(point[0], point[1])).powi(2)
+    }
+}
+
+impl TryFrom<Area> for MapFeature {
+    type Error = LoadError;
+
+    fn try_from(area: Area) -> Result<Self, Self::Error> {
+        let corrupted = |error| LoadError::corrupted(FeatureKind::Area, area.id, error);
+        Ok(Self::new(
+            area.id,
+            FeatureKind::Area,
+            area.points().map_err(corrupted)?.into(),
+            area.features().map_err(corrupted)?.into(),
+        ))
+    }
+}
+
+impl TryFrom<Way> for MapFeature {
+    type Error = LoadError;
+
+    fn try_from(way: Way) -> Result<Self, Self::Error> {
+        let corrupted = |error| LoadError::corrupted(FeatureKind::Way, way.id, error);
+        Ok(Self::new(
+            way.id,
+            FeatureKind::Way,
+            way.points().map_err(corrupted)?.into(),
+            way.features().map_err(corrupted)?.into(),
+        ))
+    }
+}
+
+impl TryFrom<Node> for MapFeature {
+    type Error = LoadError;
+
+    fn try_from(node: Node) -> Result<Self, Self::Error> {
+        let corrupted = |error| LoadError::corrupted(FeatureKind::Node, node.id, error);
+        Ok(Self::new(
+            node.id,
+            FeatureKind::Node,
+            Box::new([Point::new(node.x, node.y)]),
+            node.features().map_err(corrupted)?.into(),
+        ))
+    }
+}
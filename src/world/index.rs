@@ -0,0 +1,245 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use log::{error, info, warn};
+use rorm::conditions::DynamicCollection;
+use rorm::{and, query, Database, Model};
+use rstar::{RTree, AABB};
+use rustymon_world::geometry::Point;
+
+use crate::models::db::{Area, Node, Tile, Way};
+use crate::world::feature::{FeatureKind, MapFeature};
+use crate::world::merge::{self, Fragment};
+use crate::world::{latest_revision, DistanceThresholds, LoadError, TileAddress, ZOOM};
+
+/// Number of tile ids whose features are queried at once
+const ID_BATCH: usize = 500;
+
+/// Join the pieces of the areas or ways which were cut at the borders of the detail tiles
+fn merge(kind: FeatureKind, features: Vec<MapFeature>) -> impl Iterator<Item = MapFeature> {
+    let fragments = features
+        .into_iter()
+        .map(|feature| Fragment {
+            id: feature.id,
+            points: feature.points.into_vec(),
+            features: feature.features.into_vec(),
+        })
+        .collect();
+    let merged = match kind {
+        FeatureKind::Area => merge::merge_areas(fragments, ZOOM),
+        _ => merge::merge_ways(fragments, ZOOM),
+    };
+    merged.into_iter().map(move |fragment| {
+        MapFeature::new(
+            fragment.id,
+            kind,
+            fragment.points.into(),
+            fragment.features.into(),
+        )
+    })
+}
+
+/// In-memory R-tree of the whole world
+///
+/// It answers point lookups without the database.
+/// Areas and ways which were split at tile borders are joined again,
+/// so every element is found once.
+pub struct WorldIndex {
+    tree: RTree<MapFeature>,
+    tiles: HashSet<TileAddress>,
+    /// Latest import when the index was loaded
+    revision: Option<i64>,
+}
+
+/// Keep a decoded feature, logging it if its row is corrupted
+///
+/// A single broken row shouldn't keep the server from starting,
+/// loading it for a tile still fails the request.
+fn decoded(feature: Result<MapFeature, LoadError>) -> Option<MapFeature> {
+    feature
+        .map_err(|err| warn!("Skipping a feature of the world index: {err}"))
+        .ok()
+}
+
+impl WorldIndex {
+    /// Load every area, way and node of the full detail tiles from the database
+    ///
+    /// The features are queried in batches of detail tile ids,
+    /// corrupted rows are logged and left out.
+    pub async fn load(db: &Database) -> Result<Self, LoadError> {
+        // An import finishing while loading is picked up by the next revision check
+        let revision = latest_revision(db).await?;
+        let detail: HashMap<i64, TileAddress> = query!(db, Tile)
+            .condition(and!(
+                Tile::F.active.equals(true),
+                Tile::F.zoom.equals(i16::from(ZOOM))
+            ))
+            .all()
+            .await?
+            .into_iter()
+            .map(|tile| (tile.id, TileAddress::of(&tile)))
+            .collect();
+        let ids: Vec<i64> = detail.keys().copied().collect();
+
+        let mut areas = Vec::new();
+        let mut ways = Vec::new();
+        let mut nodes = Vec::new();
+        for batch in ids.chunks(ID_BATCH) {
+            let rows = query!(db, Area)
+                .condition(DynamicCollection::or(
+                    batch.iter().map(|&id| Area::F.tile.equals(id)).collect(),
+                ))
+                .all()
+                .await?;
+            areas.extend(
+                rows.into_iter()
+                    .filter_map(|area| decoded(MapFeature::try_from(area))),
+            );
+            let rows = query!(db, Way)
+                .condition(DynamicCollection::or(
+                    batch.iter().map(|&id| Way::F.tile.equals(id)).collect(),
+                ))
+                .all()
+                .await?;
+            ways.extend(
+                rows.into_iter()
+                    .filter_map(|way| decoded(MapFeature::try_from(way))),
+            );
+            let rows = query!(db, Node)
+                .condition(DynamicCollection::or(
+                    batch.iter().map(|&id| Node::F.tile.equals(id)).collect(),
+                ))
+                .all()
+                .await?;
+            nodes.extend(
+                rows.into_iter()
+                    .filter_map(|node| decoded(MapFeature::try_from(node))),
+            );
+        }
+
+        let features: Vec<_> = merge(FeatureKind::Area, areas)
+            .chain(merge(FeatureKind::Way, ways))
+            .chain(nodes)
+            .collect();
+
+        info!("Loaded {} features into the world index", features.len());
+        Ok(Self {
+            tree: RTree::bulk_load(features),
+            tiles: detail.into_values().collect(),
+            revision,
+        })
+    }
+
+    /// Check whether a point is covered by any tile
+    pub fn covers(&self, point: Point) -> bool {
+        self.tiles.contains(&TileAddress::containing(point, ZOOM))
+    }
+
+    /// Iterate over every feature whose bounding box intersects the square of
+    /// size `2 * distance` around `point`
+    pub fn features_around(
+        &self,
+        point: Point,
+        distance: f64,
+    ) -> impl Iterator<Item = &MapFeature> {
+        let envelope = AABB::from_corners(
+            [point.x - distance, point.y - distance],
+            [point.x + distance, point.y + distance],
+        );
+        self.tree.locate_in_envelope_intersecting(&envelope)
+    }
+
+    /// Iterate over every feature whose bounding box intersects the rectangle
+    /// spanned by two projected points
+    pub fn features_in(&self, min: Point, max: Point) -> impl Iterator<Item = &MapFeature> {
+        let envelope = AABB::from_corners([min.x, min.y], [max.x, max.y]);
+        self.tree.locate_in_envelope_intersecting(&envelope)
+    }
+
+    /// Find the features closest to `point` which are within `radius` and match `filter`
+    ///
+    /// Returns at most `limit` features with their projected distance, nearest first.
+    /// An empty filter matches every feature.
+    pub fn nearest(
+        &self,
+        point: Point,
+        radius: f64,
+        limit: usize,
+        filter: &[(u32, Option<u32>)],
+    ) -> Vec<(f64, &MapFeature)> {
+        self.tree
+            .nearest_neighbor_iter_with_distance_2(&[point.x, point.y])
+            // Features come nearest first, so the search stops at the first one outside the radius
+            .take_while(|&(_, distance_2)| distance_2 <= radius * radius)
+            .filter(|(feature, _)| filter.is_empty() || feature.matches(filter))
+            .take(limit)
+            .map(|(feature, distance_2)| (distance_2.sqrt(), feature))
+            .collect()
+    }
+
+    /// Collect the tags of every feature at `point`
+    ///
+    /// A feature is at the point if it is within its threshold.
+    /// `metres_per_unit` converts projected distances into metres at the point's latitude.
+    pub fn tags_at(
+        &self,
+        point: Point,
+        thresholds: &DistanceThresholds,
+        metres_per_unit: f64,
+    ) -> HashSet<[u32; 2]> {
+        let mut tags = HashSet::new();
+        for feature in self.features_around(point, thresholds.max() / metres_per_unit) {
+            if feature.distance_to(point) * metres_per_unit <= thresholds.of(feature) {
+                tags.extend(feature.features.iter().copied());
+            }
+        }
+        tags
+    }
+}
+
+/// The [`WorldIndex`] of the latest import
+///
+/// Requests keep using the index they started with while a new one is loaded.
+pub struct SharedWorldIndex {
+    current: RwLock<Arc<WorldIndex>>,
+}
+
+impl SharedWorldIndex {
+    pub fn new(index: WorldIndex) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(index)),
+        }
+    }
+
+    pub fn get(&self) -> Arc<WorldIndex> {
+        self.current
+            .read()
+            .expect("World index lock got poisoned")
+            .clone()
+    }
+
+    /// Reload the index whenever a new world revision has been recorded
+    ///
+    /// Checks the revision every `interval` and never returns.
+    pub async fn watch(&self, db: Database, interval: Duration) {
+        loop {
+            tokio::time::sleep(interval).await;
+
+            match latest_revision(&db).await {
+                Ok(latest) if latest != self.get().revision => {
+                    info!("The world has been re-imported, reloading the world index");
+                    match WorldIndex::load(&db).await {
+                        Ok(index) => {
+                            *self.current.write().expect("World index lock got poisoned") =
+                                Arc::new(index);
+                        }
+                        Err(err) => error!("Could not reload the world index: {err}"),
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => error!("Could not check the world revision: {err}"),
+            }
+        }
+    }
+}
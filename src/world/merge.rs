@@ -0,0 +1,527 @@
+//! Joining the pieces of areas and ways which were cut at tile borders
+//!
+//! The parser clips every element to the tiles it intersects.
+//! The pieces don't remember the element they belong to, so pieces with the same tags
+//! are joined wherever they meet on a tile border.
+
+use std::collections::{BTreeMap, HashMap};
+
+use rustymon_world::geometry::Point;
+
+/// Projected distance within which two points are considered the same
+///
+/// Both tiles compute the point where an element crosses their common border,
+/// the results may differ in the last bits. This is less than a millimetre.
+const EPSILON: f64 = 1e-12;
+
+/// A piece of an area or way
+pub struct Fragment {
+    /// Id of the row the piece was loaded from, joined pieces keep the smallest one
+    pub id: i64,
+    pub points: Vec<Point>,
+    pub features: Vec<[u32; 2]>,
+}
+
+/// Index of the tile border at `zoom` a coordinate lies on
+fn border(value: f64, zoom: u8) -> Option<i64> {
+    let tiles = f64::from(1u32 << zoom);
+    let line = (value * tiles).round();
+    ((value - line / tiles).abs() <= EPSILON).then_some(line as i64)
+}
+
+/// Check whether a point lies on the border of a tile at `zoom`
+pub fn on_border(point: Point, zoom: u8) -> bool {
+    border(point.x, zoom).is_some() || border(point.y, zoom).is_some()
+}
+
+/// Check whether `point` lies on the segment between its neighbours and can be left out
+fn redundant(previous: Point, point: Point, next: Point) -> bool {
+    let segment = next - previous;
+    let length = segment.norm();
+    let offset = point - previous;
+    length > 0.0
+        && (offset.perp(&segment) / length).abs() <= EPSILON
+        && offset.dot(&segment) > 0.0
+        && (next - point).dot(&segment) > 0.0
+}
+
+/// Points which are snapped together if they are within [`EPSILON`]
+#[derive(Default)]
+struct Vertices {
+    points: Vec<Point>,
+    cells: HashMap<(i64, i64), Vec<usize>>,
+}
+
+impl Vertices {
+    fn cell(point: Point) -> (i64, i64) {
+        (
+            (point.x / EPSILON).floor() as i64,
+            (point.y / EPSILON).floor() as i64,
+        )
+    }
+
+    /// Get the index of a point, adding it if there is no other point close to it
+    fn insert(&mut self, point: Point) -> usize {
+        let (x, y) = Self::cell(point);
+        for cell in (x - 1..=x + 1).flat_map(|x| (y - 1..=y + 1).map(move |y| (x, y))) {
+            for &index in self.cells.get(&cell).into_iter().flatten() {
+                if self.points[index].metric_distance(&point) <= EPSILON {
+                    return index;
+                }
+            }
+        }
+
+        self.points.push(point);
+        self.cells
+            .entry((x, y))
+            .or_default()
+            .push(self.points.len() - 1);
+        self.points.len() - 1
+    }
+}
+
+/// Fragments with the same tags
+type Groups = HashMap<Vec<[u32; 2]>, Vec<Fragment>>;
+
+/// Group the fragments touching a tile border by their tags
+///
+/// The other fragments are complete already and returned as they are.
+fn group(
+    fragments: Vec<Fragment>,
+    touches_border: impl Fn(&Fragment) -> bool,
+) -> (Vec<Fragment>, Groups) {
+    let mut complete = Vec::new();
+    let mut groups = Groups::new();
+    for fragment in fragments {
+        if touches_border(&fragment) {
+            groups
+                .entry(fragment.features.clone())
+                .or_default()
+                .push(fragment);
+        } else {
+            complete.push(fragment);
+        }
+    }
+    (complete, groups)
+}
+
+/// Join ways which were clipped to the tiles at `zoom`
+///
+/// A way continues with the way of the same tags which starts on the tile border it ends on.
+pub fn merge_ways(fragments: Vec<Fragment>, zoom: u8) -> Vec<Fragment> {
+    let (mut merged, groups) = group(fragments, |way| {
+        [way.points.first(), way.points.last()]
+            .into_iter()
+            .flatten()
+            .any(|&point| on_border(point, zoom))
+    });
+    for (features, ways) in groups {
+        merged.extend(chain(ways, zoom).into_iter().map(|(id, points)| Fragment {
+            id,
+            points,
+            features: features.clone(),
+        }));
+    }
+    merged
+}
+
+fn chain(mut ways: Vec<Fragment>, zoom: u8) -> Vec<(i64, Vec<Point>)> {
+    let mut vertices = Vertices::default();
+    let ends: Vec<_> = ways
+        .iter()
+        .map(|way| {
+            let start = vertices.insert(way.points[0]);
+            let end = vertices.insert(way.points[way.points.len() - 1]);
+            (start, end)
+        })
+        .collect();
+
+    let mut starting: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (index, &(start, _)) in ends.iter().enumerate() {
+        if on_border(vertices.points[start], zoom) {
+            starting.entry(start).or_default().push(index);
+        }
+    }
+    let mut continues = vec![false; ways.len()];
+    for (index, (_, end)) in ends.iter().enumerate() {
+        for &next in starting.get(end).into_iter().flatten() {
+            if next != index {
+                continues[next] = true;
+            }
+        }
+    }
+
+    // Start with the ways nothing leads to, the rest are closed loops
+    let order: Vec<_> = (0..ways.len())
+        .filter(|&index| !continues[index])
+        .chain((0..ways.len()).filter(|&index| continues[index]))
+        .collect();
+    let mut used = vec![false; ways.len()];
+    let mut chains = Vec::new();
+    for first in order {
+        if used[first] {
+            continue;
+        }
+        used[first] = true;
+        let mut id = ways[first].id;
+        let mut points = std::mem::take(&mut ways[first].points);
+
+        let mut current = first;
+        while let Some(next) = starting
+            .get(&ends[current].1)
+            .and_then(|next| next.iter().copied().find(|&index| !used[index]))
+        {
+            used[next] = true;
+            id = id.min(ways[next].id);
+            let rest = &ways[next].points[1..];
+            // The point on the border only remains if the way bends there
+            if let (&[.., previous, joint], Some(&following)) = (points.as_slice(), rest.first()) {
+                if redundant(previous, joint, following) {
+                    points.pop();
+                }
+            }
+            points.extend_from_slice(rest);
+            current = next;
+        }
+        chains.push((id, points));
+    }
+    chains
+}
+
+/// Join areas which were clipped to the tiles at `zoom`
+///
+/// Areas of the same tags which cover both sides of a tile border are dissolved into one.
+pub fn merge_areas(fragments: Vec<Fragment>, zoom: u8) -> Vec<Fragment> {
+    let (mut merged, groups) = group(fragments, |area| {
+        area.points.iter().any(|&point| on_border(point, zoom))
+    });
+    for (features, areas) in groups {
+        merged.extend(
+            dissolve(areas, zoom)
+                .into_iter()
+                .map(|(id, points)| Fragment {
+                    id,
+                    points,
+                    features: features.clone(),
+                }),
+        );
+    }
+    merged
+}
+
+/// Twice the signed area of a ring
+fn signed_area(points: &[Point]) -> f64 {
+    points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| a.x * b.y - b.x * a.y)
+        .sum()
+}
+
+/// The tile border both ends of an edge lie on
+///
+/// The first value is `true` for vertical borders.
+fn shared_border(a: Point, b: Point, zoom: u8) -> Option<(bool, i64)> {
+    match (border(a.x, zoom), border(b.x, zoom)) {
+        (Some(x), Some(other)) if x == other => return Some((true, x)),
+        _ => {}
+    }
+    match (border(a.y, zoom), border(b.y, zoom)) {
+        (Some(y), Some(other)) if y == other => Some((false, y)),
+        _ => None,
+    }
+}
+
+/// Position of a point along a vertical or horizontal border
+fn along(vertical: bool, point: Point) -> f64 {
+    if vertical {
+        point.y
+    } else {
+        point.x
+    }
+}
+
+/// Dissolve the borders between areas
+///
+/// All rings are oriented the same way, so an edge on a border which is covered
+/// on both sides appears once in each direction and both cancel out.
+/// The remaining edges are linked into rings again.
+fn dissolve(areas: Vec<Fragment>, zoom: u8) -> Vec<(i64, Vec<Point>)> {
+    let mut vertices = Vertices::default();
+    let mut edges = Vec::new();
+    for area in &areas {
+        let mut ring: Vec<_> = area
+            .points
+            .iter()
+            .map(|&point| vertices.insert(point))
+            .collect();
+        if ring.len() > 1 && ring.first() == ring.last() {
+            ring.pop();
+        }
+        let points: Vec<_> = ring.iter().map(|&index| vertices.points[index]).collect();
+        if signed_area(&points) < 0.0 {
+            ring.reverse();
+        }
+        for (index, &start) in ring.iter().enumerate() {
+            let end = ring[(index + 1) % ring.len()];
+            if start != end {
+                edges.push((start, end, area.id));
+            }
+        }
+    }
+
+    // Both sides of a border have to be split at the same vertices to cancel out
+    let mut borders: HashMap<(bool, i64), Vec<usize>> = HashMap::new();
+    for (index, point) in vertices.points.iter().enumerate() {
+        if let Some(x) = border(point.x, zoom) {
+            borders.entry((true, x)).or_default().push(index);
+        }
+        if let Some(y) = border(point.y, zoom) {
+            borders.entry((false, y)).or_default().push(index);
+        }
+    }
+    for (&(vertical, _), on_border) in borders.iter_mut() {
+        on_border.sort_by(|&a, &b| {
+            along(vertical, vertices.points[a]).total_cmp(&along(vertical, vertices.points[b]))
+        });
+    }
+
+    let mut split = Vec::with_capacity(edges.len());
+    for (start, end, id) in edges {
+        let (a, b) = (vertices.points[start], vertices.points[end]);
+        let Some(line) = shared_border(a, b, zoom) else {
+            split.push((start, end, id));
+            continue;
+        };
+        let vertical = line.0;
+        let (from, to) = (along(vertical, a), along(vertical, b));
+        let (low, high) = (from.min(to), from.max(to));
+        let on_border = &borders[&line];
+        let first = on_border.partition_point(|&v| along(vertical, vertices.points[v]) <= low);
+        let last = on_border.partition_point(|&v| along(vertical, vertices.points[v]) < high);
+        let mut inner = on_border[first..last].to_vec();
+        if from > to {
+            inner.reverse();
+        }
+
+        let mut previous = start;
+        for vertex in inner.into_iter().chain(std::iter::once(end)) {
+            if vertex != previous {
+                split.push((previous, vertex, id));
+                previous = vertex;
+            }
+        }
+    }
+
+    let mut remaining: HashMap<(usize, usize), Vec<i64>> = HashMap::new();
+    for (start, end, id) in split {
+        match remaining.get_mut(&(end, start)) {
+            Some(opposite) if !opposite.is_empty() => {
+                opposite.pop();
+            }
+            _ => remaining.entry((start, end)).or_default().push(id),
+        }
+    }
+    let mut outgoing: BTreeMap<usize, Vec<(usize, i64)>> = BTreeMap::new();
+    for ((start, end), ids) in remaining {
+        for id in ids {
+            outgoing.entry(start).or_default().push((end, id));
+        }
+    }
+
+    let mut rings = Vec::new();
+    while let Some(&start) = outgoing.keys().next() {
+        let mut ring = vec![start];
+        let mut id = i64::MAX;
+        let mut current = start;
+        while let Some(targets) = outgoing.get_mut(&current) {
+            let (next, edge) = targets.pop().expect("Empty lists are removed");
+            if targets.is_empty() {
+                outgoing.remove(&current);
+            }
+            id = id.min(edge);
+            if next == start {
+                break;
+            }
+            ring.push(next);
+            current = next;
+        }
+
+        // Drop the points the clipping added along straight edges
+        let points: Vec<_> = (0..ring.len())
+            .filter(|&index| {
+                let point = vertices.points[ring[index]];
+                let previous = vertices.points[ring[(index + ring.len() - 1) % ring.len()]];
+                let next = vertices.points[ring[(index + 1) % ring.len()]];
+                !(on_border(point, zoom) && redundant(previous, point, next))
+            })
+            .map(|index| vertices.points[ring[index]])
+            .collect();
+        if points.len() >= 3 {
+            rings.push((id, points));
+        }
+    }
+    rings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZOOM: u8 = 14;
+
+    /// Point in units of tiles at [`ZOOM`]
+    fn tile_point(x: f64, y: f64) -> Point {
+        let tiles = f64::from(1u32 << ZOOM);
+        Point::new(x / tiles, y / tiles)
+    }
+
+    fn fragment(id: i64, points: &[(f64, f64)], features: &[[u32; 2]]) -> Fragment {
+        Fragment {
+            id,
+            points: points.iter().map(|&(x, y)| tile_point(x, y)).collect(),
+            features: features.to_vec(),
+        }
+    }
+
+    fn in_tiles(points: &[Point]) -> Vec<(f64, f64)> {
+        let tiles = f64::from(1u32 << ZOOM);
+        points
+            .iter()
+            .map(|point| {
+                (
+                    (point.x * tiles * 1e6).round() / 1e6,
+                    (point.y * tiles * 1e6).round() / 1e6,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ways_are_chained_across_borders() {
+        let merged = merge_ways(
+            vec![
+                fragment(7, &[(1.0, 0.25), (2.0, 0.5)], &[[1, 2]]),
+                fragment(3, &[(0.5, 0.5), (0.75, 0.5), (1.0, 0.25)], &[[1, 2]]),
+                fragment(9, &[(2.0, 0.5), (2.5, 0.75)], &[[1, 2]]),
+            ],
+            ZOOM,
+        );
+
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].id, 3);
+        assert_eq!(
+            in_tiles(&merged[0].points),
+            [
+                (0.5, 0.5),
+                (0.75, 0.5),
+                (1.0, 0.25),
+                (2.0, 0.5),
+                (2.5, 0.75)
+            ]
+        );
+    }
+
+    #[test]
+    fn straight_joints_are_dropped() {
+        let merged = merge_ways(
+            vec![
+                fragment(1, &[(0.5, 0.5), (1.0, 0.5)], &[[0, 0]]),
+                fragment(2, &[(1.0, 0.5), (1.5, 0.5)], &[[0, 0]]),
+            ],
+            ZOOM,
+        );
+
+        assert_eq!(merged.len(), 1);
+        assert_eq!(in_tiles(&merged[0].points), [(0.5, 0.5), (1.5, 0.5)]);
+    }
+
+    #[test]
+    fn ways_with_other_tags_are_kept_apart() {
+        let merged = merge_ways(
+            vec![
+                fragment(1, &[(0.5, 0.5), (1.0, 0.5)], &[[0, 0]]),
+                fragment(2, &[(1.0, 0.5), (1.5, 0.5)], &[[0, 1]]),
+            ],
+            ZOOM,
+        );
+
+        assert_eq!(merged.len(), 2);
+    }
+
+    #[test]
+    fn areas_are_dissolved_across_borders() {
+        // A square covering the corner of four tiles, one ring in the opposite direction
+        let merged = merge_areas(
+            vec![
+                fragment(
+                    4,
+                    &[(0.5, 0.5), (1.0, 0.5), (1.0, 1.0), (0.5, 1.0)],
+                    &[[2, 0]],
+                ),
+                fragment(
+                    2,
+                    &[(1.0, 0.5), (1.5, 0.5), (1.5, 1.0), (1.0, 1.0)],
+                    &[[2, 0]],
+                ),
+                fragment(
+                    8,
+                    &[(0.5, 1.0), (1.0, 1.0), (1.0, 1.5), (0.5, 1.5)],
+                    &[[2, 0]],
+                ),
+                fragment(
+                    6,
+                    &[(1.0, 1.0), (1.0, 1.5), (1.5, 1.5), (1.5, 1.0)],
+                    &[[2, 0]],
+                ),
+            ],
+            ZOOM,
+        );
+
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].id, 2);
+        let mut points = in_tiles(&merged[0].points);
+        points.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(points, [(0.5, 0.5), (0.5, 1.5), (1.5, 0.5), (1.5, 1.5)]);
+        assert!(signed_area(&merged[0].points).abs() > 0.0);
+    }
+
+    #[test]
+    fn partially_shared_borders_are_split() {
+        // The left piece covers the whole border, the right one only its upper half
+        let merged = merge_areas(
+            vec![
+                fragment(
+                    1,
+                    &[(0.5, 0.0), (1.0, 0.0), (1.0, 1.0), (0.5, 1.0)],
+                    &[[3, 1]],
+                ),
+                fragment(2, &[(1.0, 0.0), (1.5, 0.0), (1.0, 0.5)], &[[3, 1]]),
+            ],
+            ZOOM,
+        );
+
+        assert_eq!(merged.len(), 1);
+        let mut points = in_tiles(&merged[0].points);
+        points.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(
+            points,
+            [(0.5, 0.0), (0.5, 1.0), (1.0, 0.5), (1.0, 1.0), (1.5, 0.0)]
+        );
+    }
+
+    #[test]
+    fn areas_inside_a_tile_are_kept() {
+        let merged = merge_areas(
+            vec![fragment(
+                5,
+                &[(0.25, 0.25), (0.75, 0.25), (0.5, 0.75)],
+                &[[2, 0]],
+            )],
+            ZOOM,
+        );
+
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].points.len(), 3);
+    }
+}
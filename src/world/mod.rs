@@ -1,18 +1,79 @@
 use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+use std::fmt::{Display, Formatter};
 
 use linear_map::LinearMap;
-use rorm::conditions::Condition;
-use rorm::{and, query, Database, Model};
-use rustymon_world::geometry::{polygon, polyline, Point};
+use rorm::{query, Database, Model};
+use rustymon_world::geometry::Point;
 use rustymon_world::projection::{self, Projection};
 use serde::Deserialize;
 
-use crate::models::db::{Area, Node, Tile, Way};
+use crate::models::config::WorldConfig;
+use crate::models::db::WorldRevision;
+use crate::models::geometry::DecodeError;
+
+pub use cache::TileCache;
+pub use feature::{FeatureKind, MapFeature};
+pub use index::{SharedWorldIndex, WorldIndex};
+pub use tiles::TileAddress;
+
+pub mod cache;
+pub mod clip;
+pub mod feature;
+pub mod index;
+pub mod merge;
+pub mod mvt;
+pub mod pyramid;
+pub mod tiles;
 
 pub const ZOOM: u8 = 14;
 pub static PROJECTION: projection::WebMercator = projection::WebMercator;
 pub static TAGS_FILE: &str = include_str!("../../data/spawns.json");
 
+/// Errors while loading features from the database
+#[derive(Debug)]
+pub enum LoadError {
+    Database(rorm::Error),
+    /// A stored feature couldn't be decoded
+    Corrupted {
+        kind: FeatureKind,
+        id: i64,
+        error: DecodeError,
+    },
+}
+
+impl LoadError {
+    pub(crate) fn corrupted(kind: FeatureKind, id: i64, error: DecodeError) -> Self {
+        LoadError::Corrupted { kind, id, error }
+    }
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Database(err) => write!(f, "Database error: {err}"),
+            LoadError::Corrupted { kind, id, error } => {
+                write!(f, "Could not decode {kind:?} {id}: {error}")
+            }
+        }
+    }
+}
+
+impl From<rorm::Error> for LoadError {
+    fn from(value: rorm::Error) -> Self {
+        LoadError::Database(value)
+    }
+}
+
+/// Get the id of the latest [`WorldRevision`], if the world has been imported at all
+pub async fn latest_revision(db: &Database) -> Result<Option<i64>, rorm::Error> {
+    Ok(query!(db, WorldRevision)
+        .order_desc(WorldRevision::F.id)
+        .optional()
+        .await?
+        .map(|revision| revision.id))
+}
+
 pub struct OSMTags(Vec<(&'static str, Vec<&'static str>)>);
 impl Default for OSMTags {
     /// Create a new instance by parsing the bundled file
@@ -42,6 +103,42 @@ impl OSMTags {
         }
         Some(result)
     }
+
+    /// Find the indices of a key and optionally one of its values
+    ///
+    /// Returns `None` if the key or value is unknown.
+    pub fn resolve(&self, key: &str, value: Option<&str>) -> Option<(u32, Option<u32>)> {
+        let index = self.0.iter().position(|&(k, _)| k == key)?;
+        let value = match value {
+            Some(value) => Some(self.0[index].1.iter().position(|&v| v == value)? as u32),
+            None => None,
+        };
+        Some((index as u32, value))
+    }
+}
+
+/// Latitudes beyond this can't be represented in web mercator
+pub const MAX_LATITUDE: f64 = 85.051_128_779_806_59;
+
+/// Circumference of the earth at the equator in metres
+pub const EARTH_CIRCUMFERENCE: f64 = 40_075_016.686;
+
+/// Number of metres a projected unit spans at a latitude
+///
+/// Web mercator stretches the world towards the poles,
+/// so a fixed distance in metres shrinks with the cosine of the latitude.
+pub fn metres_per_unit(lat: f64) -> f64 {
+    EARTH_CIRCUMFERENCE * lat.to_radians().cos()
+}
+
+/// Convert a projected point back to longitude and latitude
+///
+/// This is the inverse of [`PROJECTION`], which maps the world onto the unit square
+/// with `y` growing southwards.
+pub fn unproject(point: Point) -> Point {
+    let lng = point.x * 360.0 - 180.0;
+    let lat = (PI * (1.0 - 2.0 * point.y)).sinh().atan().to_degrees();
+    Point::new(lng, lat)
 }
 
 #[derive(Deserialize)]
@@ -50,59 +147,85 @@ pub struct Coord {
     pub lng: f64,
 }
 
-/// Convert a point into a condition which can be used to query the tile containing the point
-pub fn tile_condition<'a>(point: Point) -> impl Condition<'a> {
-    and!(
-        Tile::F.min_x.less_or_equals(point.x),
-        Tile::F.max_x.greater_or_equals(point.x),
-        Tile::F.min_y.less_or_equals(point.y),
-        Tile::F.max_y.greater_or_equals(point.y)
-    )
+impl Coord {
+    /// Project the coordinate into the world's coordinate system
+    ///
+    /// Returns `None` if the coordinate is out of range.
+    pub fn project(&self) -> Option<Point> {
+        if (-MAX_LATITUDE..=MAX_LATITUDE).contains(&self.lat)
+            && (-180.0..=180.0).contains(&self.lng)
+        {
+            Some(PROJECTION.project_nalgebra(Point::new(self.lng, self.lat)))
+        } else {
+            None
+        }
+    }
 }
 
-const NODE_DISTANCE: f64 = 0.0000003;
-const WAY_DISTANCE: f64 = 0.0000003;
-
-pub async fn get_osm_tags(db: &Database, coord: &Coord) -> Result<HashSet<[u32; 2]>, rorm::Error> {
-    let point = PROJECTION.project_nalgebra(Point::new(coord.lng, coord.lat));
-
-    let tiles = query!(db, Tile)
-        .condition(tile_condition(point))
-        .all()
-        .await?;
+/// Distances in metres within which a feature counts as being at a point
+pub struct DistanceThresholds {
+    node: f64,
+    way: f64,
+    /// Overrides for features with a key, indexed by the key's index
+    by_key: HashMap<u32, f64>,
+}
 
-    let mut tags = HashSet::new();
-    for tile in tiles {
-        for area in query!(db, Area)
-            .condition(Area::F.tile.equals(tile.id))
-            .all()
-            .await?
-        {
-            if polygon::contains_point(area.points(), point) {
-                tags.extend(area.features().iter().copied());
-            }
+impl DistanceThresholds {
+    /// Resolve the configured keys
+    ///
+    /// Fails if a key is unknown to the tags lookup.
+    pub(crate) fn new(config: &WorldConfig, tags: &OSMTags) -> Result<Self, String> {
+        let mut by_key = HashMap::new();
+        for (key, &distance) in &config.key_distances {
+            let (index, _) = tags
+                .resolve(key, None)
+                .ok_or_else(|| format!("Unknown OSM key in KeyDistances: {key}"))?;
+            by_key.insert(index, distance);
         }
 
-        for node in query!(db, Node)
-            .condition(Node::F.tile.equals(tile.id))
-            .all()
-            .await?
-        {
-            if point.metric_distance(&Point::new(node.x, node.y)) < NODE_DISTANCE {
-                tags.extend(node.features().iter().copied());
-            }
-        }
+        Ok(Self {
+            node: config.node_distance,
+            way: config.way_distance,
+            by_key,
+        })
+    }
 
-        for way in query!(db, Way)
-            .condition(Way::F.tile.equals(tile.id))
-            .all()
-            .await?
-        {
-            if polyline::distance_to(way.points(), point) < WAY_DISTANCE {
-                tags.extend(way.features().iter().copied());
-            }
-        }
+    /// Get the threshold of a feature
+    ///
+    /// If several of the feature's keys have an override, the largest one wins.
+    pub fn of(&self, feature: &MapFeature) -> f64 {
+        let overridden = feature
+            .features
+            .iter()
+            .filter_map(|[key, _]| self.by_key.get(key).copied())
+            .reduce(f64::max);
+        overridden.unwrap_or(match feature.kind {
+            FeatureKind::Area => 0.0,
+            FeatureKind::Way => self.way,
+            FeatureKind::Node => self.node,
+        })
     }
 
-    Ok(tags)
+    /// Get the largest threshold any feature can have
+    pub fn max(&self) -> f64 {
+        self.by_key
+            .values()
+            .copied()
+            .fold(self.node.max(self.way), f64::max)
+    }
+}
+
+/// Collect the tags of every feature at a projected point
+///
+/// Returns `None` if the point isn't covered by any tile.
+pub fn get_osm_tags(
+    index: &WorldIndex,
+    thresholds: &DistanceThresholds,
+    point: Point,
+) -> Option<HashSet<[u32; 2]>> {
+    if !index.covers(point) {
+        return None;
+    }
+    let metres_per_unit = metres_per_unit(unproject(point).y);
+    Some(index.tags_at(point, thresholds, metres_per_unit))
 }
@@ -0,0 +1,419 @@
+//! Encoder for Mapbox vector tiles
+//!
+//! Implements the parts of the [specification](https://github.com/mapbox/vector-tile-spec/tree/master/2.1)
+//! which are needed to serve the world: one layer per [`FeatureKind`] with string attributes.
+
+use std::collections::HashMap;
+
+use rustymon_world::geometry::Point;
+
+use crate::world::{clip, FeatureKind, TileAddress};
+
+/// Number of integer coordinates along a tile's edge
+pub const EXTENT: u32 = 4096;
+
+/// Margin around a tile in tile coordinates
+///
+/// Features intersecting the margin are included to avoid seams when rendering.
+pub const BUFFER: u32 = 64;
+
+/// Projected corners of a tile including its [`BUFFER`]
+pub fn buffered_bounds(address: TileAddress) -> (Point, Point) {
+    let size = address.size();
+    let buffer = size * f64::from(BUFFER) / f64::from(EXTENT);
+    let origin = address.origin();
+    (
+        Point::new(origin.x - buffer, origin.y - buffer),
+        Point::new(origin.x + size + buffer, origin.y + size + buffer),
+    )
+}
+
+mod command {
+    pub const MOVE_TO: u32 = 1;
+    pub const LINE_TO: u32 = 2;
+    pub const CLOSE_PATH: u32 = 7;
+}
+
+mod geom_type {
+    pub const POINT: u64 = 1;
+    pub const LINESTRING: u64 = 2;
+    pub const POLYGON: u64 = 3;
+}
+
+const WIRE_VARINT: u32 = 0;
+const WIRE_LENGTH_DELIMITED: u32 = 2;
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_key(buf: &mut Vec<u8>, field: u32, wire_type: u32) {
+    write_varint(buf, u64::from((field << 3) | wire_type));
+}
+
+fn write_uint(buf: &mut Vec<u8>, field: u32, value: u64) {
+    write_key(buf, field, WIRE_VARINT);
+    write_varint(buf, value);
+}
+
+fn write_bytes(buf: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    write_key(buf, field, WIRE_LENGTH_DELIMITED);
+    write_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn write_packed(buf: &mut Vec<u8>, field: u32, values: &[u32]) {
+    let mut packed = Vec::with_capacity(values.len());
+    for &value in values {
+        write_varint(&mut packed, u64::from(value));
+    }
+    write_bytes(buf, field, &packed);
+}
+
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+/// Geometry encoding state of a single feature
+struct Geometry {
+    commands: Vec<u32>,
+    cursor: [i32; 2],
+}
+
+impl Geometry {
+    fn new() -> Self {
+        Self {
+            commands: Vec::new(),
+            cursor: [0, 0],
+        }
+    }
+
+    fn command(&mut self, id: u32, count: usize) {
+        self.commands.push(id | ((count as u32) << 3));
+    }
+
+    fn point(&mut self, [x, y]: [i32; 2]) {
+        self.commands.push(zigzag(x - self.cursor[0]));
+        self.commands.push(zigzag(y - self.cursor[1]));
+        self.cursor = [x, y];
+    }
+
+    fn path(&mut self, points: &[[i32; 2]]) {
+        self.command(command::MOVE_TO, 1);
+        self.point(points[0]);
+        self.command(command::LINE_TO, points.len() - 1);
+        for &point in &points[1..] {
+            self.point(point);
+        }
+    }
+}
+
+/// A single layer of a tile
+struct Layer {
+    name: &'static str,
+    features: Vec<u8>,
+    keys: Vec<&'static str>,
+    key_index: HashMap<&'static str, u32>,
+    values: Vec<String>,
+    value_index: HashMap<String, u32>,
+}
+
+impl Layer {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            features: Vec::new(),
+            keys: Vec::new(),
+            key_index: HashMap::new(),
+            values: Vec::new(),
+            value_index: HashMap::new(),
+        }
+    }
+
+    fn key(&mut self, key: &'static str) -> u32 {
+        *self.key_index.entry(key).or_insert_with(|| {
+            self.keys.push(key);
+            self.keys.len() as u32 - 1
+        })
+    }
+
+    fn value(&mut self, value: String) -> u32 {
+        if let Some(&index) = self.value_index.get(&value) {
+            return index;
+        }
+        let index = self.values.len() as u32;
+        self.values.push(value.clone());
+        self.value_index.insert(value, index);
+        index
+    }
+
+    fn add(
+        &mut self,
+        geometry_type: u64,
+        geometry: Geometry,
+        tags: &HashMap<&'static str, Vec<&'static str>>,
+    ) {
+        let mut attributes = Vec::with_capacity(tags.len() * 2);
+        for (&key, values) in tags {
+            // OSM joins multiple values of the same key with semicolons
+            let key = self.key(key);
+            let value = self.value(values.join(";"));
+            attributes.push(key);
+            attributes.push(value);
+        }
+
+        let mut feature = Vec::new();
+        write_packed(&mut feature, 2, &attributes);
+        write_uint(&mut feature, 3, geometry_type);
+        write_packed(&mut feature, 4, &geometry.commands);
+        write_bytes(&mut self.features, 2, &feature);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.features.is_empty()
+    }
+
+    fn encode(self, buf: &mut Vec<u8>) {
+        let mut layer = Vec::new();
+        write_uint(&mut layer, 15, 2);
+        write_bytes(&mut layer, 1, self.name.as_bytes());
+        layer.extend_from_slice(&self.features);
+        for key in self.keys {
+            write_bytes(&mut layer, 3, key.as_bytes());
+        }
+        for value in self.values {
+            let mut encoded = Vec::new();
+            write_bytes(&mut encoded, 1, value.as_bytes());
+            write_bytes(&mut layer, 4, &encoded);
+        }
+        write_uint(&mut layer, 5, u64::from(EXTENT));
+        write_bytes(buf, 3, &layer);
+    }
+}
+
+/// Builder for a single vector tile
+///
+/// Areas, ways and nodes end up in the layers `areas`, `ways` and `nodes`.
+/// Geometry is cut to the tile and its [`BUFFER`].
+pub struct TileEncoder {
+    origin: Point,
+    scale: f64,
+    bounds: (Point, Point),
+    areas: Layer,
+    ways: Layer,
+    nodes: Layer,
+}
+
+impl TileEncoder {
+    pub fn new(address: TileAddress) -> Self {
+        Self {
+            origin: address.origin(),
+            scale: f64::from(EXTENT) / address.size(),
+            bounds: buffered_bounds(address),
+            areas: Layer::new("areas"),
+            ways: Layer::new("ways"),
+            nodes: Layer::new("nodes"),
+        }
+    }
+
+    /// Convert projected points into tile coordinates
+    ///
+    /// Consecutive points which end up at the same coordinate are merged.
+    fn quantize(&self, points: &[Point]) -> Vec<[i32; 2]> {
+        let mut quantized: Vec<[i32; 2]> = Vec::with_capacity(points.len());
+        for point in points {
+            let point = [
+                ((point.x - self.origin.x) * self.scale).round() as i32,
+                ((point.y - self.origin.y) * self.scale).round() as i32,
+            ];
+            if quantized.last() != Some(&point) {
+                quantized.push(point);
+            }
+        }
+        quantized
+    }
+
+    /// Add a feature to the layer of its kind
+    ///
+    /// Features which degenerate at this zoom level are skipped.
+    pub fn add(
+        &mut self,
+        kind: FeatureKind,
+        points: &[Point],
+        tags: &HashMap<&'static str, Vec<&'static str>>,
+    ) {
+        let (min, max) = self.bounds;
+        let mut geometry = Geometry::new();
+        match kind {
+            FeatureKind::Area => {
+                let mut points = self.quantize(&clip::clip_ring(points, min, max));
+                if points.len() > 1 && points.first() == points.last() {
+                    points.pop();
+                }
+                if points.len() < 3 {
+                    return;
+                }
+
+                // Exterior rings have to be clockwise, i.e. have a positive area in tile coordinates
+                let area: i64 = points
+                    .iter()
+                    .zip(points.iter().cycle().skip(1))
+                    .map(|(a, b)| {
+                        i64::from(a[0]) * i64::from(b[1]) - i64::from(b[0]) * i64::from(a[1])
+                    })
+                    .sum();
+                if area == 0 {
+                    return;
+                }
+                if area < 0 {
+                    points.reverse();
+                }
+
+                geometry.path(&points);
+                geometry.command(command::CLOSE_PATH, 1);
+                self.areas.add(geom_type::POLYGON, geometry, tags);
+            }
+            FeatureKind::Way => {
+                // A way leaving the tile and coming back becomes a multi line string
+                let parts: Vec<_> = clip::clip_line(points, min, max)
+                    .iter()
+                    .map(|part| self.quantize(part))
+                    .filter(|part| part.len() >= 2)
+                    .collect();
+                if parts.is_empty() {
+                    return;
+                }
+                for part in &parts {
+                    geometry.path(part);
+                }
+                self.ways.add(geom_type::LINESTRING, geometry, tags);
+            }
+            FeatureKind::Node => {
+                let Some(&point) = self.quantize(points).first() else {
+                    return;
+                };
+                geometry.command(command::MOVE_TO, 1);
+                geometry.point(point);
+                self.nodes.add(geom_type::POINT, geometry, tags);
+            }
+        }
+    }
+
+    /// Encode the tile, leaving out empty layers
+    pub fn finish(self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for layer in [self.areas, self.ways, self.nodes] {
+            if !layer.is_empty() {
+                layer.encode(&mut buf);
+            }
+        }
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_varint(bytes: &mut &[u8]) -> u64 {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let (&byte, rest) = bytes.split_first().expect("varint should be terminated");
+            *bytes = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte < 0x80 {
+                return value;
+            }
+            shift += 7;
+        }
+    }
+
+    /// Geometry commands of every feature in a layer
+    fn commands(layer: &Layer) -> Vec<Vec<u32>> {
+        let mut features = Vec::new();
+        let mut layer = layer.features.as_slice();
+        while !layer.is_empty() {
+            assert_eq!(read_varint(&mut layer), 2 << 3 | 2);
+            let len = read_varint(&mut layer) as usize;
+            let (mut feature, rest) = layer.split_at(len);
+            layer = rest;
+
+            while !feature.is_empty() {
+                let key = read_varint(&mut feature);
+                if key & 7 == 0 {
+                    read_varint(&mut feature);
+                    continue;
+                }
+                let len = read_varint(&mut feature) as usize;
+                let (mut packed, rest) = feature.split_at(len);
+                feature = rest;
+                if key >> 3 == 4 {
+                    let mut commands = Vec::new();
+                    while !packed.is_empty() {
+                        commands.push(read_varint(&mut packed) as u32);
+                    }
+                    features.push(commands);
+                }
+            }
+        }
+        features
+    }
+
+    fn points(points: &[(f64, f64)]) -> Vec<Point> {
+        points.iter().map(|&(x, y)| Point::new(x, y)).collect()
+    }
+
+    #[test]
+    fn zigzag_interleaves_signs() {
+        assert_eq!(zigzag(0), 0);
+        assert_eq!(zigzag(-1), 1);
+        assert_eq!(zigzag(1), 2);
+        assert_eq!(zigzag(-2), 3);
+        assert_eq!(zigzag(2), 4);
+        assert_eq!(zigzag(i32::MAX), u32::MAX - 1);
+        assert_eq!(zigzag(i32::MIN), u32::MAX);
+    }
+
+    #[test]
+    fn areas_are_encoded_clockwise() {
+        let counter_clockwise = points(&[(0.25, 0.25), (0.25, 0.5), (0.5, 0.5), (0.5, 0.25)]);
+        let clockwise: Vec<_> = counter_clockwise.iter().rev().copied().collect();
+
+        let mut encoder = TileEncoder::new(TileAddress { z: 0, x: 0, y: 0 });
+        encoder.add(FeatureKind::Area, &counter_clockwise, &HashMap::new());
+        encoder.add(FeatureKind::Area, &clockwise, &HashMap::new());
+
+        // (2048, 1024) -> (2048, 2048) -> (1024, 2048) -> (1024, 1024)
+        let expected = vec![9, 4096, 2048, 26, 0, 2048, 2047, 0, 0, 2047, 15];
+        assert_eq!(commands(&encoder.areas), [expected.clone(), expected]);
+    }
+
+    #[test]
+    fn degenerate_areas_are_skipped() {
+        let mut encoder = TileEncoder::new(TileAddress { z: 0, x: 0, y: 0 });
+        encoder.add(
+            FeatureKind::Area,
+            &points(&[(0.25, 0.25), (0.5, 0.5), (0.75, 0.75)]),
+            &HashMap::new(),
+        );
+
+        assert!(encoder.areas.is_empty());
+    }
+
+    #[test]
+    fn ways_use_relative_coordinates() {
+        let mut encoder = TileEncoder::new(TileAddress { z: 0, x: 0, y: 0 });
+        encoder.add(
+            FeatureKind::Way,
+            &points(&[(0.5, 0.5), (0.25, 0.5)]),
+            &HashMap::new(),
+        );
+
+        assert_eq!(commands(&encoder.ways), [vec![9, 4096, 4096, 10, 2047, 0]]);
+    }
+}
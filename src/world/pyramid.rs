@@ -0,0 +1,268 @@
+//! Coarse zoom levels generated from the full detail tiles
+//!
+//! The pieces the parser cut at the borders of the tiles at [`ZOOM`] are joined first,
+//! so every element is simplified as a whole and cut into the coarse tiles afterwards.
+//! Every level below [`ZOOM`] down to [`MIN_ZOOM`] is built that way.
+//! Geometry is simplified to the precision a vector tile of the level can display,
+//! nodes and features which shrink below that precision are dropped.
+
+use std::collections::HashMap;
+
+use rustymon_world::geometry::Point;
+
+use crate::world::merge::{self, Fragment};
+use crate::world::mvt::EXTENT;
+use crate::world::{clip, TileAddress, ZOOM};
+
+/// Lowest zoom level generated at import
+pub const MIN_ZOOM: u8 = 10;
+
+/// Geometry and tags of a single area or way
+pub struct Shape {
+    pub points: Vec<Point>,
+    pub features: Vec<[u32; 2]>,
+}
+
+/// Areas and ways of a single tile
+pub struct PyramidTile {
+    pub address: TileAddress,
+    pub areas: Vec<Shape>,
+    pub ways: Vec<Shape>,
+}
+
+impl PyramidTile {
+    pub fn new(address: TileAddress) -> Self {
+        Self {
+            address,
+            areas: Vec::new(),
+            ways: Vec::new(),
+        }
+    }
+}
+
+fn fragments<'a>(shapes: impl Iterator<Item = &'a Shape>) -> Vec<Fragment> {
+    shapes
+        .map(|shape| Fragment {
+            id: 0,
+            points: shape.points.clone(),
+            features: shape.features.clone(),
+        })
+        .collect()
+}
+
+fn shapes(fragments: Vec<Fragment>) -> Vec<Shape> {
+    fragments
+        .into_iter()
+        .map(|fragment| Shape {
+            points: fragment.points,
+            features: fragment.features,
+        })
+        .collect()
+}
+
+/// Addresses of the tiles at `zoom` the bounding box of the points intersects
+fn covered_tiles(points: &[Point], zoom: u8) -> impl Iterator<Item = TileAddress> {
+    let mut min = Point::new(f64::INFINITY, f64::INFINITY);
+    let mut max = Point::new(f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for point in points {
+        min = Point::new(min.x.min(point.x), min.y.min(point.y));
+        max = Point::new(max.x.max(point.x), max.y.max(point.y));
+    }
+    let min = TileAddress::containing(min, zoom);
+    let max = TileAddress::containing(max, zoom);
+    (min.x..=max.x).flat_map(move |x| (min.y..=max.y).map(move |y| TileAddress { z: zoom, x, y }))
+}
+
+/// Generate the coarse levels from the tiles at [`ZOOM`]
+///
+/// A coarse tile is generated for every tile at a lower zoom level which contains a detail tile.
+/// Each level is simplified from the full detail geometry to avoid accumulating errors.
+pub fn build(detail: &[&PyramidTile]) -> Vec<PyramidTile> {
+    let areas = shapes(merge::merge_areas(
+        fragments(detail.iter().flat_map(|tile| &tile.areas)),
+        ZOOM,
+    ));
+    let ways = shapes(merge::merge_ways(
+        fragments(detail.iter().flat_map(|tile| &tile.ways)),
+        ZOOM,
+    ));
+
+    let mut levels = Vec::new();
+    for zoom in (MIN_ZOOM..ZOOM).rev() {
+        let shift = ZOOM - zoom;
+        let tolerance = 1.0 / f64::from(1u32 << zoom) / f64::from(EXTENT);
+
+        let mut tiles: HashMap<TileAddress, PyramidTile> = HashMap::new();
+        for tile in detail {
+            let address = TileAddress {
+                z: zoom,
+                x: tile.address.x >> shift,
+                y: tile.address.y >> shift,
+            };
+            tiles
+                .entry(address)
+                .or_insert_with(|| PyramidTile::new(address));
+        }
+
+        for area in areas
+            .iter()
+            .filter_map(|area| simplify_ring(area, tolerance))
+        {
+            for address in covered_tiles(&area.points, zoom) {
+                let Some(tile) = tiles.get_mut(&address) else {
+                    continue;
+                };
+                let (min, max) = bounds(address);
+                let points = clip::clip_ring(&area.points, min, max);
+                if points.len() >= 3 && extent(&points) >= tolerance {
+                    tile.areas.push(Shape {
+                        points,
+                        features: area.features.clone(),
+                    });
+                }
+            }
+        }
+        for way in ways.iter().filter_map(|way| simplify_line(way, tolerance)) {
+            for address in covered_tiles(&way.points, zoom) {
+                let Some(tile) = tiles.get_mut(&address) else {
+                    continue;
+                };
+                let (min, max) = bounds(address);
+                for points in clip::clip_line(&way.points, min, max) {
+                    if extent(&points) >= tolerance {
+                        tile.ways.push(Shape {
+                            points,
+                            features: way.features.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        levels.extend(tiles.into_values());
+    }
+    levels
+}
+
+/// Projected corners of a tile
+fn bounds(address: TileAddress) -> (Point, Point) {
+    let origin = address.origin();
+    let size = address.size();
+    (origin, Point::new(origin.x + size, origin.y + size))
+}
+
+/// Simplify a way, dropping it if it is shorter than `tolerance`
+fn simplify_line(way: &Shape, tolerance: f64) -> Option<Shape> {
+    let points = simplify_pinned(&way.points, tolerance);
+    if points.len() < 2 || extent(&points) < tolerance {
+        return None;
+    }
+    Some(Shape {
+        points,
+        features: way.features.clone(),
+    })
+}
+
+/// Simplify an area, dropping it if it is smaller than `tolerance`
+fn simplify_ring(area: &Shape, tolerance: f64) -> Option<Shape> {
+    let mut ring = area.points.clone();
+    let closed = ring.len() > 1 && ring.first() == ring.last();
+    if !closed {
+        if let Some(&first) = ring.first() {
+            ring.push(first);
+        }
+    }
+
+    let mut points = simplify_pinned(&ring, tolerance);
+    if points.len() < 4 || extent(&points) < tolerance {
+        return None;
+    }
+    if !closed {
+        points.pop();
+    }
+    Some(Shape {
+        points,
+        features: area.features.clone(),
+    })
+}
+
+/// Length of the diagonal of the points' bounding box
+fn extent(points: &[Point]) -> f64 {
+    let mut min = [f64::INFINITY; 2];
+    let mut max = [f64::NEG_INFINITY; 2];
+    for point in points {
+        min = [min[0].min(point.x), min[1].min(point.y)];
+        max = [max[0].max(point.x), max[1].max(point.y)];
+    }
+    (max[0] - min[0]).hypot(max[1] - min[1])
+}
+
+/// Distance between `point` and the segment from `start` to `end`
+fn segment_distance(point: Point, start: Point, end: Point) -> f64 {
+    let segment = end - start;
+    let length = segment.norm_squared();
+    if length == 0.0 {
+        return point.metric_distance(&start);
+    }
+    let t = ((point - start).dot(&segment) / length).clamp(0.0, 1.0);
+    point.metric_distance(&(start + segment * t))
+}
+
+/// Simplify a polyline, keeping its points on the borders of the tiles at [`MIN_ZOOM`]
+///
+/// Shapes are only joined within a tile at [`MIN_ZOOM`], keeping the points on its border
+/// lets them meet the shapes simplified in the neighbouring tiles.
+fn simplify_pinned(points: &[Point], tolerance: f64) -> Vec<Point> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+
+    let mut simplified: Vec<Point> = Vec::with_capacity(points.len());
+    let mut start = 0;
+    for end in 1..points.len() {
+        if end == points.len() - 1 || merge::on_border(points[end], MIN_ZOOM) {
+            let part = simplify(&points[start..=end], tolerance);
+            // Each part starts with the point the previous one ended with
+            let skip = usize::from(!simplified.is_empty());
+            simplified.extend_from_slice(&part[skip..]);
+            start = end;
+        }
+    }
+    simplified
+}
+
+/// Simplify a polyline with the Douglas-Peucker algorithm
+///
+/// The first and last point are always kept.
+pub fn simplify(points: &[Point], tolerance: f64) -> Vec<Point> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+
+    let mut stack = vec![(0, points.len() - 1)];
+    while let Some((start, end)) = stack.pop() {
+        let mut farthest = (0.0, start);
+        for (index, &point) in points.iter().enumerate().take(end).skip(start + 1) {
+            let distance = segment_distance(point, points[start], points[end]);
+            if distance > farthest.0 {
+                farthest = (distance, index);
+            }
+        }
+
+        let (distance, index) = farthest;
+        if distance > tolerance {
+            keep[index] = true;
+            stack.push((start, index));
+            stack.push((index, end));
+        }
+    }
+
+    points
+        .iter()
+        .zip(keep)
+        .filter_map(|(&point, keep)| keep.then_some(point))
+        .collect()
+}
@@ -0,0 +1,108 @@
+use rorm::{and, query, Database, Model};
+use rustymon_world::geometry::Point;
+use serde::Deserialize;
+
+use crate::models::db::{Area, Node, Tile, Way};
+use crate::world::feature::MapFeature;
+use crate::world::LoadError;
+
+/// Address of a tile in the slippy map scheme
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub struct TileAddress {
+    pub z: u8,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl TileAddress {
+    /// Highest zoom level a tile can be requested at
+    pub const MAX_ZOOM: u8 = 22;
+
+    /// Get the tile at zoom level `z` which contains a projected point
+    ///
+    /// Points on the world's right or bottom edge belong to the last tile.
+    pub fn containing(point: Point, z: u8) -> Self {
+        let tiles = 1u32 << z;
+        let index =
+            |value: f64| ((value * f64::from(tiles)).floor().max(0.0) as u32).min(tiles - 1);
+        Self {
+            z,
+            x: index(point.x),
+            y: index(point.y),
+        }
+    }
+
+    /// Get the address of a tile stored in the database
+    pub fn of(tile: &Tile) -> Self {
+        Self {
+            z: tile.zoom as u8,
+            x: tile.x as u32,
+            y: tile.y as u32,
+        }
+    }
+
+    /// Check whether the tile exists
+    pub fn is_valid(&self) -> bool {
+        self.z <= Self::MAX_ZOOM && self.x < 1 << self.z && self.y < 1 << self.z
+    }
+
+    /// Edge length of the tile in projected coordinates
+    pub fn size(&self) -> f64 {
+        1.0 / f64::from(1u32 << self.z)
+    }
+
+    /// Projected top left corner of the tile
+    pub fn origin(&self) -> Point {
+        let size = self.size();
+        Point::new(f64::from(self.x) * size, f64::from(self.y) * size)
+    }
+}
+
+/// Find every stored tile of a zoom level which intersects the rectangle spanned by two projected points
+pub async fn tiles_in_box(
+    db: &Database,
+    zoom: u8,
+    min: Point,
+    max: Point,
+) -> Result<Vec<Tile>, rorm::Error> {
+    let min = TileAddress::containing(min, zoom);
+    let max = TileAddress::containing(max, zoom);
+    query!(db, Tile)
+        .condition(and!(
+            Tile::F.active.equals(true),
+            Tile::F.zoom.equals(i16::from(zoom)),
+            Tile::F.x.greater_or_equals(i64::from(min.x)),
+            Tile::F.x.less_or_equals(i64::from(max.x)),
+            Tile::F.y.greater_or_equals(i64::from(min.y)),
+            Tile::F.y.less_or_equals(i64::from(max.y))
+        ))
+        .all()
+        .await
+}
+
+/// Load and decode every area, way and node of a tile
+pub async fn load_tile(db: &Database, tile: i64) -> Result<Vec<MapFeature>, LoadError> {
+    let mut features = Vec::new();
+    for area in query!(db, Area)
+        .condition(Area::F.tile.equals(tile))
+        .all()
+        .await?
+    {
+        features.push(MapFeature::try_from(area)?);
+    }
+    for way in query!(db, Way)
+        .condition(Way::F.tile.equals(tile))
+        .all()
+        .await?
+    {
+        features.push(MapFeature::try_from(way)?);
+    }
+    for node in query!(db, Node)
+        .condition(Node::F.tile.equals(tile))
+        .all()
+        .await?
+    {
+        features.push(MapFeature::try_from(node)?);
+    }
+    Ok(features)
+}